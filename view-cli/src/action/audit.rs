@@ -0,0 +1,63 @@
+use clap::Args;
+
+use crate::client::{AuditQuery, ViewClient};
+
+#[derive(Args)]
+pub(crate) struct AuditAction {
+  /// Only events of this actor, e.g. token:<id>
+  #[clap(long)]
+  actor: Option<String>,
+  /// Only events of calls made with the token of this id
+  #[clap(long)]
+  token: Option<String>,
+  /// Only events of this action, e.g. environment.publish
+  #[clap(long)]
+  action: Option<String>,
+  /// Only events affecting this target, e.g. an environment name
+  #[clap(long)]
+  target: Option<String>,
+  /// Only events at or after this RFC 3339 timestamp
+  #[clap(long)]
+  since: Option<String>,
+  /// Only events before this RFC 3339 timestamp
+  #[clap(long)]
+  until: Option<String>,
+  /// Page to show, starting at 0
+  #[clap(long, default_value_t = 0)]
+  page: u64,
+  #[clap(long, default_value_t = 50)]
+  per_page: u64,
+}
+
+impl AuditAction {
+  pub(crate) async fn execute(self, client: ViewClient) -> anyhow::Result<()> {
+    let query = AuditQuery {
+      actor: self.actor,
+      token_id: self.token,
+      action: self.action,
+      target: self.target,
+      since: self.since,
+      until: self.until,
+      page: self.page,
+      per_page: self.per_page,
+    };
+
+    let result = client.audit(&query).await?;
+
+    for event in result.events {
+      println!(
+        "{}  {}  {}  {}  {}  {}",
+        event.created,
+        event.actor,
+        event.action,
+        event.target.as_deref().unwrap_or("-"),
+        event.status,
+        event.remote_addr.as_deref().unwrap_or("-"),
+      );
+    }
+
+    println!("Page {} of {}", result.page + 1, result.pages.max(1));
+
+    Ok(())
+  }
+}
@@ -6,8 +6,9 @@ use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use tracing::{info, warn};
 
-use crate::client::{FileData, ViewClient};
+use crate::client::{ErrorPageData, FileData, ViewClient};
 use crate::git::{get_commit_description, get_commit_id};
+use crate::rules::{parse_headers, parse_redirects};
 
 #[derive(Args)]
 pub(crate) struct DeployAction {
@@ -15,10 +16,30 @@ pub(crate) struct DeployAction {
   upload_dir: PathBuf,
   #[clap(short, long, env = "VIEW_FALLBACK_FILE")]
   fallback: Vec<String>,
+  /// File with header rules in the format of `_headers` files
+  #[clap(long, env = "VIEW_HEADERS_FILE")]
+  headers: Option<PathBuf>,
+  /// File with redirect rules in the format of `_redirects` files
+  #[clap(long, env = "VIEW_REDIRECTS_FILE")]
+  redirects: Option<PathBuf>,
+  /// Page served for an error status, e.g. `404=/404.html`, a page for 500
+  /// covers all 5xx statuses
+  #[clap(long, env = "VIEW_ERROR_PAGES", value_delimiter = ',', value_parser = parse_error_page)]
+  error_page: Vec<ErrorPageData>,
 }
 
 impl DeployAction {
   pub(crate) async fn execute(self, client: ViewClient) -> anyhow::Result<()> {
+    let headers = match &self.headers {
+      Some(path) => parse_headers(&tokio::fs::read_to_string(path).await?)?,
+      None => Vec::new(),
+    };
+
+    let redirects = match &self.redirects {
+      Some(path) => parse_redirects(&tokio::fs::read_to_string(path).await?)?,
+      None => Vec::new(),
+    };
+
     let paths = find_files(self.upload_dir.clone()).await?;
     info!("Found {} files to upload", paths.len());
 
@@ -60,7 +81,14 @@ impl DeployAction {
     let commit_description = get_commit_description(&commit_id).await?;
 
     let objects_to_upload = client
-      .put_commit(&commit_id, &commit_description, &files)
+      .put_commit(
+        &commit_id,
+        &commit_description,
+        &files,
+        &headers,
+        &redirects,
+        &self.error_page,
+      )
       .await?;
 
     for FileData { object_id, .. } in objects_to_upload {
@@ -78,10 +106,29 @@ impl DeployAction {
       }
     }
 
+    let commit = client.finalize_commit(&commit_id).await?;
+
+    if let Some(preview_url) = commit.preview_url {
+      info!("Preview available at {}", preview_url);
+    }
+
     Ok(())
   }
 }
 
+fn parse_error_page(value: &str) -> Result<ErrorPageData, String> {
+  let (status, path) = value
+    .split_once('=')
+    .ok_or_else(|| "expected STATUS=PATH".to_string())?;
+
+  Ok(ErrorPageData {
+    status: status
+      .parse()
+      .map_err(|_| format!("invalid status {}", status))?,
+    path: path.to_string(),
+  })
+}
+
 async fn find_files(root: PathBuf) -> anyhow::Result<Vec<PathBuf>> {
   let mut out = Vec::new();
   let mut to_visit = vec![root];
@@ -1,17 +1,23 @@
 use clap::Subcommand;
 
+use crate::action::audit::AuditAction;
 use crate::action::deploy::DeployAction;
 use crate::action::publish::PublishAction;
+use crate::action::rollback::RollbackAction;
 use crate::client::ViewClient;
 use crate::GeneralArgs;
 
+mod audit;
 mod deploy;
 mod publish;
+mod rollback;
 
 #[derive(Subcommand)]
 pub(crate) enum Action {
   Deploy(DeployAction),
   Publish(PublishAction),
+  Rollback(RollbackAction),
+  Audit(AuditAction),
 }
 
 impl Action {
@@ -21,6 +27,8 @@ impl Action {
     match self {
       Action::Deploy(action) => action.execute(client).await,
       Action::Publish(action) => action.execute(client).await,
+      Action::Rollback(action) => action.execute(client).await,
+      Action::Audit(action) => action.execute(client).await,
     }
   }
 }
@@ -1,12 +1,37 @@
 use clap::Args;
+use tracing::info;
 
 use crate::client::ViewClient;
+use crate::git::get_commit_id;
 
 #[derive(Args)]
-pub(crate) struct PublishAction {}
+pub(crate) struct PublishAction {
+  #[clap(short, long, env = "VIEW_ENVIRONMENT")]
+  environment: String,
+  /// Defaults to the currently checked out commit
+  #[clap(short, long, env = "VIEW_COMMIT")]
+  commit: Option<String>,
+}
 
 impl PublishAction {
   pub(crate) async fn execute(self, client: ViewClient) -> anyhow::Result<()> {
+    let commit_id = match self.commit {
+      Some(commit_id) => commit_id,
+      None => get_commit_id().await?,
+    };
+
+    info!(
+      "Publishing commit {} to environment {}...",
+      commit_id, self.environment
+    );
+
+    let environment = client.publish(&self.environment, &commit_id).await?;
+
+    info!(
+      "Environment {} ({}) is now serving commit {}",
+      environment.name, environment.domain, environment.commit_id
+    );
+
     Ok(())
   }
 }
@@ -0,0 +1,31 @@
+use clap::Args;
+use tracing::info;
+
+use crate::client::ViewClient;
+
+#[derive(Args)]
+pub(crate) struct RollbackAction {
+  #[clap(short, long, env = "VIEW_ENVIRONMENT")]
+  environment: String,
+  /// Number of deployments to go back
+  #[clap(short, long, default_value_t = 1)]
+  steps: u64,
+}
+
+impl RollbackAction {
+  pub(crate) async fn execute(self, client: ViewClient) -> anyhow::Result<()> {
+    info!(
+      "Rolling back environment {} by {} deployment(s)...",
+      self.environment, self.steps
+    );
+
+    let environment = client.rollback(&self.environment, self.steps).await?;
+
+    info!(
+      "Environment {} ({}) is now serving commit {}",
+      environment.name, environment.domain, environment.commit_id
+    );
+
+    Ok(())
+  }
+}
@@ -1,7 +1,12 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::anyhow;
 use hex_buffer_serde::{ConstHex, ConstHexForm};
 use reqwest::multipart::{Form, Part};
-use reqwest::{Body, Client};
+use reqwest::{Body, Client, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tokio::fs::File;
 use tokio_util::codec::{BytesCodec, FramedRead};
 use url::Url;
@@ -16,6 +21,9 @@ pub(crate) struct ViewClient {
 pub(crate) struct CommitData<'a> {
   description: &'a str,
   files: &'a [FileData],
+  headers: &'a [HeaderRuleData],
+  redirects: &'a [RedirectRuleData],
+  error_pages: &'a [ErrorPageData],
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -26,33 +34,181 @@ pub(crate) struct FileData {
   pub(crate) fallback: bool,
 }
 
+#[derive(Serialize, Clone)]
+pub(crate) struct HeaderRuleData {
+  pub(crate) path: String,
+  pub(crate) name: String,
+  pub(crate) value: String,
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct RedirectRuleData {
+  pub(crate) source: String,
+  pub(crate) destination: String,
+  pub(crate) status: u16,
+  pub(crate) query: BTreeMap<String, String>,
+  pub(crate) host: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct ErrorPageData {
+  pub(crate) status: u16,
+  pub(crate) path: String,
+}
+
+#[derive(Serialize)]
+struct PublishData<'a> {
+  commit_id: &'a str,
+}
+
+#[derive(Serialize)]
+struct RollbackData {
+  steps: u64,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct EnvironmentData {
+  pub(crate) name: String,
+  pub(crate) domain: String,
+  pub(crate) commit_id: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CommitInfoData {
+  pub(crate) preview_url: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct AuditQuery {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) actor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) token_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) action: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) target: Option<String>,
+  /// RFC 3339 timestamps.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) since: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) until: Option<String>,
+  pub(crate) page: u64,
+  pub(crate) per_page: u64,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AuditEventData {
+  pub(crate) created: String,
+  pub(crate) actor: String,
+  pub(crate) action: String,
+  pub(crate) target: Option<String>,
+  pub(crate) remote_addr: Option<String>,
+  pub(crate) status: u16,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AuditPageData {
+  pub(crate) events: Vec<AuditEventData>,
+  pub(crate) page: u64,
+  pub(crate) pages: u64,
+}
+
+/// The error body of view-management.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiError {
+  #[serde(skip)]
+  pub(crate) status: StatusCode,
+  pub(crate) code: String,
+  pub(crate) message: String,
+  pub(crate) details: Option<Value>,
+}
+
+impl fmt::Display for ApiError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ({}, {})", self.message, self.code, self.status)?;
+
+    match &self.details {
+      Some(Value::Null) | None => Ok(()),
+      Some(details) => write!(f, ": {}", details),
+    }
+  }
+}
+
+impl std::error::Error for ApiError {}
+
 impl ViewClient {
   pub(crate) fn new(base_url: Url, token: String) -> Self {
     Self {
       client: Client::new(),
-      base_url: base_url.join("v1").unwrap(),
+      base_url: base_url.join("v1/").unwrap(),
       token,
     }
   }
 
+  /// Sends the request with the token, error responses are turned into an
+  /// [`ApiError`].
+  async fn send(&self, request: RequestBuilder) -> anyhow::Result<Response> {
+    let response = request.bearer_auth(&self.token).send().await?;
+
+    let status = response.status();
+    if status.is_success() {
+      return Ok(response);
+    }
+
+    let body = response.text().await?;
+    match serde_json::from_str::<ApiError>(&body) {
+      Ok(mut err) => {
+        err.status = status;
+        Err(err.into())
+      }
+      Err(_) => Err(anyhow!("Request failed with {}: {}", status, body)),
+    }
+  }
+
   pub(crate) async fn put_commit(
     &self,
     id: &str,
     description: &str,
     files: &[FileData],
+    headers: &[HeaderRuleData],
+    redirects: &[RedirectRuleData],
+    error_pages: &[ErrorPageData],
   ) -> anyhow::Result<Vec<FileData>> {
-    let data = CommitData { description, files };
+    let data = CommitData {
+      description,
+      files,
+      headers,
+      redirects,
+      error_pages,
+    };
 
-    let result = self
+    let request = self
       .client
       .put(self.base_url.join(&format!("commit/{}", id))?)
-      .bearer_auth(&self.token)
-      .json(&data)
-      .send()
-      .await?
-      .error_for_status()?
-      .json::<Vec<FileData>>()
-      .await?;
+      .json(&data);
+
+    let result = self.send(request).await?.json::<Vec<FileData>>().await?;
+
+    Ok(result)
+  }
+
+  /// Marks the commit as complete once all objects are uploaded, it fails if
+  /// some are still missing.
+  pub(crate) async fn finalize_commit(&self, id: &str) -> anyhow::Result<CommitInfoData> {
+    let request = self
+      .client
+      .post(self.base_url.join(&format!("commit/{}/finalize", id))?);
+
+    let result = self.send(request).await?.json::<CommitInfoData>().await?;
+
+    Ok(result)
+  }
+
+  pub(crate) async fn audit(&self, query: &AuditQuery) -> anyhow::Result<AuditPageData> {
+    let request = self.client.get(self.base_url.join("audit")?).query(query);
+
+    let result = self.send(request).await?.json::<AuditPageData>().await?;
 
     Ok(result)
   }
@@ -65,15 +221,55 @@ impl ViewClient {
     let part = Part::stream_with_length(Body::wrap_stream(stream), len);
     let form = Form::new().part("file", part);
 
-    self
+    let request = self
       .client
       .put(self.base_url.join(&format!("object/{}", id))?)
-      .bearer_auth(&self.token)
-      .multipart(form)
-      .send()
-      .await?
-      .error_for_status()?;
+      .multipart(form);
+
+    self.send(request).await?;
 
     Ok(())
   }
+
+  pub(crate) async fn publish(
+    &self,
+    environment: &str,
+    commit_id: &str,
+  ) -> anyhow::Result<EnvironmentData> {
+    let data = PublishData { commit_id };
+
+    let request = self
+      .client
+      .post(
+        self
+          .base_url
+          .join(&format!("environment/{}/publish", environment))?,
+      )
+      .json(&data);
+
+    let result = self.send(request).await?.json::<EnvironmentData>().await?;
+
+    Ok(result)
+  }
+
+  pub(crate) async fn rollback(
+    &self,
+    environment: &str,
+    steps: u64,
+  ) -> anyhow::Result<EnvironmentData> {
+    let data = RollbackData { steps };
+
+    let request = self
+      .client
+      .post(
+        self
+          .base_url
+          .join(&format!("environment/{}/rollback", environment))?,
+      )
+      .json(&data);
+
+    let result = self.send(request).await?.json::<EnvironmentData>().await?;
+
+    Ok(result)
+  }
 }
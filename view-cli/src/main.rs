@@ -8,6 +8,7 @@ use crate::action::Action;
 mod action;
 mod client;
 mod git;
+mod rules;
 
 #[derive(Parser)]
 #[command(version)]
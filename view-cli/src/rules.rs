@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail};
+use url::Url;
+
+use crate::client::{HeaderRuleData, RedirectRuleData};
+
+/// Parses header rules in the format of `_headers` files, a path glob
+/// followed by indented `Name: value` lines:
+///
+/// ```text
+/// # hashed assets never change
+/// /assets/*
+///   Cache-Control: public, max-age=31536000, immutable
+/// ```
+pub(crate) fn parse_headers(content: &str) -> anyhow::Result<Vec<HeaderRuleData>> {
+  let mut rules = Vec::new();
+  let mut path = None::<&str>;
+
+  for (idx, line) in content.lines().enumerate() {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+
+    if !line.starts_with(char::is_whitespace) {
+      if !trimmed.starts_with('/') {
+        bail!("line {}: expected a path starting with /", idx + 1);
+      }
+
+      path = Some(trimmed);
+      continue;
+    }
+
+    let path = path.ok_or_else(|| anyhow!("line {}: header without a path", idx + 1))?;
+    let (name, value) = trimmed
+      .split_once(':')
+      .ok_or_else(|| anyhow!("line {}: expected Name: value", idx + 1))?;
+
+    rules.push(HeaderRuleData {
+      path: path.to_string(),
+      name: name.trim().to_string(),
+      value: value.trim().to_string(),
+    });
+  }
+
+  Ok(rules)
+}
+
+/// Parses redirect rules in the format of `_redirects` files, one rule per
+/// line with the source, optional query conditions, the destination and an
+/// optional status, which defaults to 301:
+///
+/// ```text
+/// /blog/:year/:slug  /news/:year/:slug
+/// /search  q=:query  /find?term=:query  302
+/// https://old.example.com/*  https://example.com/:splat  308
+/// /app/*  /app/index.html  200
+/// ```
+pub(crate) fn parse_redirects(content: &str) -> anyhow::Result<Vec<RedirectRuleData>> {
+  let mut rules = Vec::new();
+
+  for (idx, line) in content.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let mut tokens = line.split_whitespace();
+
+    // an absolute source only matches requests for its host
+    let source = tokens.next().unwrap_or_default();
+    let (host, source) = if source.starts_with("http://") || source.starts_with("https://") {
+      let url = Url::parse(source).map_err(|err| anyhow!("line {}: {}", idx + 1, err))?;
+      (url.host_str().map(str::to_string), url.path().to_string())
+    } else {
+      (None, source.to_string())
+    };
+
+    let mut query = BTreeMap::new();
+    let destination = loop {
+      let token = tokens
+        .next()
+        .ok_or_else(|| anyhow!("line {}: expected a destination", idx + 1))?;
+
+      match token.split_once('=') {
+        Some((key, value)) if !token.starts_with('/') && !token.contains("://") => {
+          query.insert(key.to_string(), value.to_string());
+        }
+        _ => break token.to_string(),
+      }
+    };
+
+    // all rules apply even if the source exists, so `!` changes nothing
+    let status = match tokens.next() {
+      Some(status) => status
+        .trim_end_matches('!')
+        .parse()
+        .map_err(|_| anyhow!("line {}: invalid status {}", idx + 1, status))?,
+      None => 301,
+    };
+
+    if let Some(token) = tokens.next() {
+      bail!("line {}: unexpected {}", idx + 1, token);
+    }
+
+    rules.push(RedirectRuleData {
+      source,
+      destination,
+      status,
+      query,
+      host,
+    });
+  }
+
+  Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_headers() {
+    let rules = parse_headers(
+      "# hashed assets never change
+/assets/*
+  Cache-Control: public, max-age=31536000, immutable
+
+  X-Robots-Tag:noindex
+/*
+\tLink: </style.css>; rel=preload; as=style
+",
+    )
+    .unwrap();
+
+    let rules = rules
+      .iter()
+      .map(|rule| (rule.path.as_str(), rule.name.as_str(), rule.value.as_str()))
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      rules,
+      [
+        (
+          "/assets/*",
+          "Cache-Control",
+          "public, max-age=31536000, immutable"
+        ),
+        ("/assets/*", "X-Robots-Tag", "noindex"),
+        ("/*", "Link", "</style.css>; rel=preload; as=style"),
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_malformed_headers() {
+    for (content, error) in [
+      (
+        "assets/*\n  A: b",
+        "line 1: expected a path starting with /",
+      ),
+      ("  A: b", "line 1: header without a path"),
+      ("/*\n  A b", "line 2: expected Name: value"),
+    ] {
+      let err = parse_headers(content).err().unwrap();
+      assert_eq!(err.to_string(), error);
+    }
+  }
+
+  #[test]
+  fn parses_redirects() {
+    let rules = parse_redirects(
+      "# moved
+/blog/:year/:slug  /news/:year/:slug
+
+/search  q=:query  page=1  /find?term=:query  302
+https://old.example.com/*  https://example.com/:splat  308
+/app/*  /app/index.html  200!
+",
+    )
+    .unwrap();
+
+    assert_eq!(rules.len(), 4);
+
+    assert_eq!(rules[0].source, "/blog/:year/:slug");
+    assert_eq!(rules[0].destination, "/news/:year/:slug");
+    assert_eq!(rules[0].status, 301);
+    assert!(rules[0].query.is_empty());
+    assert_eq!(rules[0].host, None);
+
+    assert_eq!(rules[1].source, "/search");
+    assert_eq!(rules[1].destination, "/find?term=:query");
+    assert_eq!(rules[1].status, 302);
+    assert_eq!(
+      rules[1].query,
+      BTreeMap::from([
+        ("page".to_string(), "1".to_string()),
+        ("q".to_string(), ":query".to_string()),
+      ])
+    );
+
+    assert_eq!(rules[2].source, "/*");
+    assert_eq!(rules[2].destination, "https://example.com/:splat");
+    assert_eq!(rules[2].status, 308);
+    assert_eq!(rules[2].host.as_deref(), Some("old.example.com"));
+
+    assert_eq!(rules[3].destination, "/app/index.html");
+    assert_eq!(rules[3].status, 200);
+  }
+
+  #[test]
+  fn rejects_malformed_redirects() {
+    for (content, error) in [
+      ("/old", "line 1: expected a destination"),
+      ("\n/old  q=1", "line 2: expected a destination"),
+      ("/old  /new  moved", "line 1: invalid status moved"),
+      ("/old  /new  99999", "line 1: invalid status 99999"),
+      ("/old  /new  301  extra", "line 1: unexpected extra"),
+    ] {
+      let err = parse_redirects(content).err().unwrap();
+      assert_eq!(err.to_string(), error);
+    }
+
+    assert!(parse_redirects("https://exa mple.com/old  /new").is_err());
+  }
+}
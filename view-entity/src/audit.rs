@@ -0,0 +1,31 @@
+use sea_orm::prelude::*;
+use time::OffsetDateTime;
+
+/// A mutating call of the management api. Events are only ever appended.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "audit")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub id: Uuid,
+  pub created: OffsetDateTime,
+  pub actor: String,
+  /// `None` for the static bootstrap token.
+  pub token_id: Option<Uuid>,
+  /// e.g. `environment.publish`
+  pub action: String,
+  /// Ids of the affected resources separated by `/`, e.g. `prod/www.example.com`.
+  pub target: Option<String>,
+  pub method: String,
+  #[sea_orm(column_type = "Text")]
+  pub path: String,
+  pub remote_addr: Option<String>,
+  #[sea_orm(column_type = "Text", nullable)]
+  pub user_agent: Option<String>,
+  /// Status of the response.
+  pub status: i16,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -10,6 +10,22 @@ pub struct Model {
   #[sea_orm(column_type = "Text")]
   pub description: String,
   pub created: OffsetDateTime,
+  pub status: CommitStatus,
+}
+
+/// Whether all objects of the commit have been uploaded, only complete
+/// commits are published and served.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum CommitStatus {
+  /// Objects are still being uploaded.
+  #[sea_orm(string_value = "pending")]
+  Pending,
+  #[sea_orm(string_value = "complete")]
+  Complete,
+  /// The commit was finalized before all objects were uploaded.
+  #[sea_orm(string_value = "failed")]
+  Failed,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -18,6 +34,12 @@ pub enum Relation {
   File,
   #[sea_orm(has_many = "super::environment::Entity")]
   Environment,
+  #[sea_orm(has_many = "super::header_rule::Entity")]
+  HeaderRule,
+  #[sea_orm(has_many = "super::redirect_rule::Entity")]
+  RedirectRule,
+  #[sea_orm(has_many = "super::error_page::Entity")]
+  ErrorPage,
 }
 
 impl Related<super::file::Entity> for Entity {
@@ -32,4 +54,22 @@ impl Related<super::environment::Entity> for Entity {
   }
 }
 
+impl Related<super::header_rule::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::HeaderRule.def()
+  }
+}
+
+impl Related<super::redirect_rule::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::RedirectRule.def()
+  }
+}
+
+impl Related<super::error_page::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::ErrorPage.def()
+  }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,36 @@
+use sea_orm::prelude::*;
+use time::OffsetDateTime;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "deployment")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub id: Uuid,
+  /// Increases with every deployment, unlike `created` it never repeats.
+  pub sequence: i64,
+  pub environment_id: Uuid,
+  pub old_commit_id: Option<Vec<u8>>, // [u8; 20]
+  pub new_commit_id: Vec<u8>,         // [u8; 20]
+  pub created: OffsetDateTime,
+  pub actor: String,
+  /// Restored an earlier commit, rolling back further skips these.
+  pub rollback: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "super::environment::Entity",
+    from = "Column::EnvironmentId",
+    to = "super::environment::Column::Id"
+  )]
+  Environment,
+}
+
+impl Related<super::environment::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Environment.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
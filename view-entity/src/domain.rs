@@ -0,0 +1,31 @@
+use sea_orm::prelude::*;
+
+/// An additional domain of an environment, next to its primary domain.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "domain")]
+pub struct Model {
+  /// A host name, or `*.` followed by one to match any single label.
+  #[sea_orm(primary_key)]
+  pub name: String,
+  pub environment_id: Uuid,
+  /// Redirect requests to the primary domain instead of serving them.
+  pub redirect: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "super::environment::Entity",
+    from = "Column::EnvironmentId",
+    to = "super::environment::Column::Id"
+  )]
+  Environment,
+}
+
+impl Related<super::environment::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Environment.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
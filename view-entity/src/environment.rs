@@ -6,8 +6,26 @@ pub struct Model {
   #[sea_orm(primary_key)]
   pub id: Uuid,
   pub name: String,
+  /// The primary domain, see [`super::domain`] for the others.
   pub domain: String,
   pub commit_id: Vec<u8>, // [u8; 20]
+  pub trailing_slash: TrailingSlash,
+  /// Serve `/about` from `/about.html`.
+  pub clean_urls: bool,
+}
+
+/// Which form of directory and clean urls is canonical, requests for the
+/// other one are redirected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum TrailingSlash {
+  /// Serve both forms.
+  #[sea_orm(string_value = "preserve")]
+  Preserve,
+  #[sea_orm(string_value = "always")]
+  Always,
+  #[sea_orm(string_value = "never")]
+  Never,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -18,6 +36,10 @@ pub enum Relation {
     to = "super::commit::Column::Id"
   )]
   Commit,
+  #[sea_orm(has_many = "super::deployment::Entity")]
+  Deployment,
+  #[sea_orm(has_many = "super::domain::Entity")]
+  Domain,
 }
 
 impl Related<super::commit::Entity> for Entity {
@@ -26,4 +48,16 @@ impl Related<super::commit::Entity> for Entity {
   }
 }
 
+impl Related<super::deployment::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Deployment.def()
+  }
+}
+
+impl Related<super::domain::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Domain.def()
+  }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,31 @@
+use sea_orm::prelude::*;
+
+/// A file of the commit served as the body of error responses.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "error_page")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub commit_id: Vec<u8>, // [u8; 20]
+  /// 404 or a 5xx status, 500 also covers the other 5xx statuses.
+  #[sea_orm(primary_key)]
+  pub status: i16,
+  pub path: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "super::commit::Entity",
+    from = "Column::CommitId",
+    to = "super::commit::Column::Id"
+  )]
+  Commit,
+}
+
+impl Related<super::commit::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Commit.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,34 @@
+use sea_orm::prelude::*;
+
+/// A header added to every response of the commit whose path matches the
+/// glob. Rules are applied in the order of their position.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "header_rule")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub commit_id: Vec<u8>, // [u8; 20]
+  #[sea_orm(primary_key)]
+  pub position: i32,
+  pub path: String,
+  pub name: String,
+  #[sea_orm(column_type = "Text")]
+  pub value: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "super::commit::Entity",
+    from = "Column::CommitId",
+    to = "super::commit::Column::Id"
+  )]
+  Commit,
+}
+
+impl Related<super::commit::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Commit.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
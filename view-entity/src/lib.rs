@@ -1,4 +1,12 @@
+pub mod audit;
 pub mod commit;
+pub mod deployment;
+pub mod domain;
 pub mod environment;
+pub mod error_page;
 pub mod file;
+pub mod header_rule;
 pub mod object;
+pub mod object_variant;
+pub mod redirect_rule;
+pub mod token;
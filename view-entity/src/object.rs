@@ -16,6 +16,8 @@ pub struct Model {
 pub enum Relation {
   #[sea_orm(has_many = "super::file::Entity")]
   File,
+  #[sea_orm(has_many = "super::object_variant::Entity")]
+  ObjectVariant,
 }
 
 impl Related<super::file::Entity> for Entity {
@@ -24,4 +26,10 @@ impl Related<super::file::Entity> for Entity {
   }
 }
 
+impl Related<super::object_variant::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::ObjectVariant.def()
+  }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,31 @@
+use sea_orm::prelude::*;
+
+/// A compressed copy of an object, stored next to it.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "object_variant")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub object_id: Vec<u8>, // [u8; 32]
+  /// Content coding, as used in `Content-Encoding`.
+  #[sea_orm(primary_key)]
+  pub encoding: String,
+  pub size: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "super::object::Entity",
+    from = "Column::ObjectId",
+    to = "super::object::Column::Id"
+  )]
+  Object,
+}
+
+impl Related<super::object::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Object.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,41 @@
+use sea_orm::prelude::*;
+
+/// Redirects or rewrites requests of the commit matching the source pattern.
+/// Rules are evaluated in the order of their position, the first match wins.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "redirect_rule")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub commit_id: Vec<u8>, // [u8; 20]
+  #[sea_orm(primary_key)]
+  pub position: i32,
+  /// Path with `:placeholder` segments and an optional trailing `*` splat.
+  pub source: String,
+  #[sea_orm(column_type = "Text")]
+  pub destination: String,
+  /// 301, 302, 307 or 308 for redirects, 200 for rewrites.
+  pub status: i16,
+  /// Required query parameters as `key=value&…`, values starting with `:`
+  /// are placeholders.
+  #[sea_orm(column_type = "Text", nullable)]
+  pub query: Option<String>,
+  pub host: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "super::commit::Entity",
+    from = "Column::CommitId",
+    to = "super::commit::Column::Id"
+  )]
+  Commit,
+}
+
+impl Related<super::commit::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Commit.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
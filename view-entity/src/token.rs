@@ -0,0 +1,26 @@
+use sea_orm::prelude::*;
+use time::OffsetDateTime;
+
+/// A credential for the management api, limited to its scopes.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "token")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub id: Uuid,
+  pub name: String,
+  /// SHA-256 of the secret, the secret itself is only shown on creation.
+  pub secret_hash: Vec<u8>, // [u8; 32]
+  /// Space separated, e.g. `commit:write environment:prod:publish`.
+  #[sea_orm(column_type = "Text")]
+  pub scopes: String,
+  pub created: OffsetDateTime,
+  pub last_used: Option<OffsetDateTime>,
+  pub expires: Option<OffsetDateTime>,
+  /// Revoked tokens are kept, so they can still be attributed.
+  pub revoked: Option<OffsetDateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
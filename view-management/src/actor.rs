@@ -0,0 +1,163 @@
+use axum::async_trait;
+use axum::extract::{FromRequestParts, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+  ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel,
+  QueryFilter,
+};
+use sha2::{Digest, Sha256};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use view_entity::token;
+
+use crate::error::Error;
+
+/// Identifies the caller of a management endpoint without exposing its token.
+#[derive(Clone)]
+pub(crate) struct Actor {
+  /// Recorded as the author of deployments.
+  pub(crate) name: String,
+  /// `None` for the static bootstrap token.
+  pub(crate) token_id: Option<Uuid>,
+  scopes: Vec<String>,
+}
+
+/// A permission a token has to be granted to call an endpoint. `admin`
+/// grants all of them.
+pub(crate) enum Scope<'a> {
+  Admin,
+  CommitWrite,
+  ObjectWrite,
+  /// Publishing and rolling back the named environment.
+  Publish(&'a str),
+}
+
+impl Scope<'_> {
+  fn granted_by(&self, scope: &str) -> bool {
+    if scope == "admin" {
+      return true;
+    }
+
+    match self {
+      Scope::Admin => false,
+      Scope::CommitWrite => scope == "commit:write",
+      Scope::ObjectWrite => scope == "object:write",
+      Scope::Publish(environment) => {
+        publish_scope(scope).is_some_and(|granted| granted == "*" || granted == *environment)
+      }
+    }
+  }
+}
+
+/// The environment name of an `environment:<name>:publish` scope.
+pub(crate) fn publish_scope(scope: &str) -> Option<&str> {
+  scope.strip_prefix("environment:")?.strip_suffix(":publish")
+}
+
+impl Actor {
+  pub(crate) fn require(&self, scope: Scope) -> Result<(), Error> {
+    if self.scopes.iter().any(|granted| scope.granted_by(granted)) {
+      Ok(())
+    } else {
+      Err(Error::Forbidden)
+    }
+  }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Actor {
+  type Rejection = Error;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    parts
+      .extensions
+      .get::<Actor>()
+      .cloned()
+      .ok_or(Error::Unauthorized)
+  }
+}
+
+/// How outdated the last use of a token may be.
+const LAST_USED_PRECISION: Duration = Duration::minutes(1);
+
+#[derive(Clone)]
+pub(crate) struct Authenticator {
+  pub(crate) db: DatabaseConnection,
+  /// SHA-256 of the static token, which is granted `admin`.
+  pub(crate) bootstrap_hash: [u8; 32],
+}
+
+/// Resolves the bearer token of the request to its [`Actor`], requests
+/// without a valid token are rejected.
+pub(crate) async fn authenticate<B>(
+  State(authenticator): State<Authenticator>,
+  mut req: Request<B>,
+  next: Next<B>,
+) -> Result<Response, Error> {
+  let secret = req
+    .headers()
+    .get(AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .ok_or(Error::Unauthorized)?;
+
+  let digest: [u8; 32] = Sha256::digest(secret.as_bytes()).into();
+
+  let actor = if digest == authenticator.bootstrap_hash {
+    Actor {
+      name: format!("token:{}", hex::encode(&digest[..4])),
+      token_id: None,
+      scopes: vec!["admin".to_string()],
+    }
+  } else {
+    let now = OffsetDateTime::now_utc();
+
+    let token = token::Entity::find()
+      .filter(
+        Condition::all()
+          .add(token::Column::SecretHash.eq(digest.to_vec()))
+          .add(token::Column::Revoked.is_null())
+          .add(
+            Condition::any()
+              .add(token::Column::Expires.is_null())
+              .add(token::Column::Expires.gt(now)),
+          ),
+      )
+      .one(&authenticator.db)
+      .await?
+      .ok_or(Error::Unauthorized)?;
+
+    let actor = Actor {
+      name: format!("token:{}", token.id),
+      token_id: Some(token.id),
+      scopes: token
+        .scopes
+        .split_whitespace()
+        .map(str::to_string)
+        .collect(),
+    };
+
+    // only roughly tracked, so reads do not turn into writes
+    let stale = token
+      .last_used
+      .is_none_or(|last_used| now - last_used > LAST_USED_PRECISION);
+
+    if stale {
+      let mut token = token.into_active_model();
+      token.last_used = Set(Some(now));
+      token.update(&authenticator.db).await?;
+    }
+
+    actor
+  };
+
+  req.extensions_mut().insert(actor);
+
+  Ok(next.run(req).await)
+}
@@ -0,0 +1,229 @@
+use std::net::SocketAddr;
+
+use axum::debug_handler;
+use axum::extract::{ConnectInfo, MatchedPath, Path, State};
+use axum::http::header::USER_AGENT;
+use axum::http::{Method, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+  ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait,
+  QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use view_entity::audit;
+
+use crate::actor::{Actor, Scope};
+use crate::error::Error;
+use crate::extract::{Json, Query};
+use crate::ManagementState;
+
+const MAX_PER_PAGE: u64 = 100;
+
+/// Names the created resource for the audit log, if the request path does
+/// not contain its id.
+#[derive(Clone)]
+pub(crate) struct AuditTarget(pub(crate) String);
+
+/// Appends an event for every mutating call after it has been handled,
+/// failed ones included. Must run after the caller has been authenticated.
+pub(crate) async fn record<B>(
+  State(db): State<DatabaseConnection>,
+  matched_path: MatchedPath,
+  params: Option<Path<Vec<(String, String)>>>,
+  connect_info: Option<ConnectInfo<SocketAddr>>,
+  req: Request<B>,
+  next: Next<B>,
+) -> Response {
+  let method = req.method().clone();
+  if matches!(method, Method::GET | Method::HEAD | Method::OPTIONS) {
+    return next.run(req).await;
+  }
+
+  let actor = req.extensions().get::<Actor>().cloned();
+  let path = req.uri().path().to_string();
+  let user_agent = req
+    .headers()
+    .get(USER_AGENT)
+    .and_then(|value| value.to_str().ok())
+    .map(str::to_string);
+
+  let response = next.run(req).await;
+
+  let Some(actor) = actor else {
+    return response;
+  };
+
+  let target = match response.extensions().get::<AuditTarget>() {
+    Some(AuditTarget(target)) => Some(target.clone()),
+    None => params.map(|Path(params)| {
+      params
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect::<Vec<_>>()
+        .join("/")
+    }),
+  };
+
+  let event = audit::ActiveModel {
+    id: Set(Uuid::new_v4()),
+    created: Set(OffsetDateTime::now_utc()),
+    actor: Set(actor.name),
+    token_id: Set(actor.token_id),
+    action: Set(action(&method, matched_path.as_str())),
+    target: Set(target.filter(|target| !target.is_empty())),
+    method: Set(method.to_string()),
+    path: Set(path),
+    remote_addr: Set(connect_info.map(|ConnectInfo(addr)| addr.ip().to_string())),
+    user_agent: Set(user_agent),
+    status: Set(response.status().as_u16() as i16),
+  };
+
+  // the call itself already happened, so it is not failed after the fact
+  if let Err(err) = event.insert(&db).await {
+    eprint!("Error: {:?}", err);
+  }
+
+  response
+}
+
+fn action(method: &Method, route: &str) -> String {
+  let action = match (method.as_str(), route) {
+    ("PUT", "/v1/commit/:id") => "commit.create",
+    ("POST", "/v1/commit/:id/finalize") => "commit.finalize",
+    ("PUT", "/v1/object/:id") => "object.upload",
+    ("POST", "/v1/environment") => "environment.create",
+    ("PATCH", "/v1/environment/:name") => "environment.update",
+    ("DELETE", "/v1/environment/:name") => "environment.delete",
+    ("POST", "/v1/environment/:name/publish") => "environment.publish",
+    ("POST", "/v1/environment/:name/rollback") => "environment.rollback",
+    ("PUT", "/v1/environment/:name/domain/:domain") => "domain.put",
+    ("DELETE", "/v1/environment/:name/domain/:domain") => "domain.delete",
+    ("POST", "/v1/token") => "token.create",
+    ("DELETE", "/v1/token/:id") => "token.revoke",
+    _ => return format!("{} {}", method, route),
+  };
+
+  action.to_string()
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AuditQuery {
+  actor: Option<String>,
+  token_id: Option<Uuid>,
+  action: Option<String>,
+  target: Option<String>,
+  #[serde(default, with = "time::serde::rfc3339::option")]
+  since: Option<OffsetDateTime>,
+  #[serde(default, with = "time::serde::rfc3339::option")]
+  until: Option<OffsetDateTime>,
+  /// Starts at 0.
+  #[serde(default)]
+  page: u64,
+  #[serde(default = "default_per_page")]
+  per_page: u64,
+}
+
+fn default_per_page() -> u64 {
+  50
+}
+
+#[derive(Serialize)]
+pub(crate) struct AuditEventData {
+  id: Uuid,
+  #[serde(with = "time::serde::rfc3339")]
+  created: OffsetDateTime,
+  actor: String,
+  token_id: Option<Uuid>,
+  action: String,
+  target: Option<String>,
+  method: String,
+  path: String,
+  remote_addr: Option<String>,
+  user_agent: Option<String>,
+  status: u16,
+}
+
+impl From<audit::Model> for AuditEventData {
+  fn from(event: audit::Model) -> Self {
+    Self {
+      id: event.id,
+      created: event.created,
+      actor: event.actor,
+      token_id: event.token_id,
+      action: event.action,
+      target: event.target,
+      method: event.method,
+      path: event.path,
+      remote_addr: event.remote_addr,
+      user_agent: event.user_agent,
+      status: event.status as u16,
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub(crate) struct AuditPageData {
+  events: Vec<AuditEventData>,
+  page: u64,
+  pages: u64,
+}
+
+/// Lists the events matching all given filters, newest first.
+#[debug_handler]
+pub(crate) async fn list(
+  State(state): State<ManagementState>,
+  actor: Actor,
+  Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditPageData>, Error> {
+  actor.require(Scope::Admin)?;
+
+  if query.per_page == 0 || query.per_page > MAX_PER_PAGE {
+    return Err(Error::InvalidPageSize);
+  }
+
+  let mut condition = Condition::all();
+
+  if let Some(actor) = query.actor {
+    condition = condition.add(audit::Column::Actor.eq(actor));
+  }
+
+  if let Some(token_id) = query.token_id {
+    condition = condition.add(audit::Column::TokenId.eq(token_id));
+  }
+
+  if let Some(action) = query.action {
+    condition = condition.add(audit::Column::Action.eq(action));
+  }
+
+  if let Some(target) = query.target {
+    condition = condition.add(audit::Column::Target.eq(target));
+  }
+
+  if let Some(since) = query.since {
+    condition = condition.add(audit::Column::Created.gte(since));
+  }
+
+  if let Some(until) = query.until {
+    condition = condition.add(audit::Column::Created.lt(until));
+  }
+
+  let paginator = audit::Entity::find()
+    .filter(condition)
+    .order_by_desc(audit::Column::Created)
+    .order_by_desc(audit::Column::Id)
+    .paginate(&state.db, query.per_page);
+
+  let pages = paginator.num_pages().await?;
+  let events = paginator.fetch_page(query.page).await?;
+
+  Ok(Json(AuditPageData {
+    events: events.into_iter().map(Into::into).collect(),
+    page: query.page,
+    pages,
+  }))
+}
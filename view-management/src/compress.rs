@@ -0,0 +1,149 @@
+use std::io::{self, Write};
+
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use futures_util::TryStreamExt;
+use mime_guess::{mime, Mime};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+  ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+};
+
+use view_entity::{file, object, object_variant};
+use view_store::{Encoding, ObjectStore};
+
+/// Below this size the framing of the encodings eats up what they save.
+const MIN_SIZE: u64 = 256;
+
+/// Objects are compressed in memory, larger ones are only served as they are.
+const MAX_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Compressible types besides `text/*` and the `+json` and `+xml` suffixes.
+const COMPRESSIBLE: &[&str] = &[
+  "application/javascript",
+  "application/json",
+  "application/wasm",
+  "application/xml",
+  "font/otf",
+  "font/ttf",
+  "image/bmp",
+  "image/vnd.microsoft.icon",
+  "image/x-icon",
+];
+
+/// Whether the object should have compressed variants, which is the case if
+/// any of its paths has a compressible type.
+pub(crate) async fn needs_variants(
+  db: &DatabaseConnection,
+  id: &[u8],
+  size: u64,
+) -> Result<bool, DbErr> {
+  if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+    return Ok(false);
+  }
+
+  let files = file::Entity::find()
+    .filter(file::Column::ObjectId.eq(id.to_vec()))
+    .all(db)
+    .await?;
+
+  Ok(
+    files
+      .iter()
+      .any(|file| is_compressible(&mime_guess::from_path(&file.path).first_or_octet_stream())),
+  )
+}
+
+/// Stores a compressed variant of the object in every encoding that makes it
+/// smaller. Previously stored variants are replaced. Only storing them takes
+/// the lock uploads take, compressing happens before without any lock.
+pub(crate) async fn store_variants(
+  db: &DatabaseConnection,
+  store: &dyn ObjectStore,
+  id: &[u8],
+  size: u64,
+) -> anyhow::Result<()> {
+  let content = match store.get(id, None).await? {
+    Some(stream) => {
+      stream
+        .try_fold(
+          Vec::with_capacity(size as usize),
+          |mut content, chunk| async move {
+            content.extend_from_slice(&chunk);
+            Ok(content)
+          },
+        )
+        .await?
+    }
+    None => return Ok(()),
+  };
+
+  let variants = tokio::task::spawn_blocking(move || compress(&content)).await??;
+
+  let tx = db.begin().await?;
+
+  // the object may have been reset or deleted while compressing
+  let object = object::Entity::find_by_id(id.to_vec())
+    .lock_exclusive()
+    .one(&tx)
+    .await?;
+  if object.and_then(|object| object.size) != Some(size as i64) {
+    return Ok(());
+  }
+
+  object_variant::Entity::delete_many()
+    .filter(object_variant::Column::ObjectId.eq(id.to_vec()))
+    .exec(&tx)
+    .await?;
+
+  for (encoding, content) in variants {
+    let variant = object_variant::ActiveModel {
+      object_id: Set(id.to_vec()),
+      encoding: Set(encoding.name().to_string()),
+      size: Set(content.len() as i64),
+    };
+
+    store
+      .put_variant(id, encoding, Bytes::from(content))
+      .await?;
+    object_variant::Entity::insert(variant).exec(&tx).await?;
+  }
+
+  Ok(tx.commit().await?)
+}
+
+fn is_compressible(mime: &Mime) -> bool {
+  mime.type_() == mime::TEXT
+    || matches!(mime.suffix(), Some(mime::JSON) | Some(mime::XML))
+    || COMPRESSIBLE.contains(&mime.essence_str())
+}
+
+/// Compresses the content with the best settings of every encoding, as it
+/// only happens once per object. Variants that are not smaller are dropped.
+fn compress(content: &[u8]) -> io::Result<Vec<(Encoding, Vec<u8>)>> {
+  let mut variants = Vec::new();
+
+  for encoding in Encoding::ALL {
+    let compressed = match encoding {
+      Encoding::Brotli => {
+        let mut compressed = Vec::new();
+        let mut writer = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+        writer.write_all(content)?;
+        drop(writer);
+        compressed
+      }
+      Encoding::Zstd => zstd::encode_all(content, 19)?,
+      Encoding::Gzip => {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(content)?;
+        encoder.finish()?
+      }
+    };
+
+    if compressed.len() < content.len() {
+      variants.push((encoding, compressed));
+    }
+  }
+
+  Ok(variants)
+}
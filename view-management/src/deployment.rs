@@ -0,0 +1,162 @@
+use axum::debug_handler;
+use axum::extract::State;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+  ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, EntityTrait, NotSet, QueryFilter,
+  QueryOrder, QuerySelect, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use view_entity::{deployment, environment};
+
+use crate::actor::{Actor, Scope};
+use crate::environment::{find_environment, switch_commit, EnvironmentData};
+use crate::error::Error;
+use crate::extract::{Json, Path};
+use crate::ManagementState;
+
+#[derive(Deserialize)]
+pub(crate) struct RollbackData {
+  #[serde(default = "default_steps")]
+  steps: u64,
+}
+
+fn default_steps() -> u64 {
+  1
+}
+
+#[derive(Serialize)]
+pub(crate) struct DeploymentData {
+  id: Uuid,
+  old_commit_id: Option<String>,
+  new_commit_id: String,
+  #[serde(with = "time::serde::rfc3339")]
+  created: OffsetDateTime,
+  actor: String,
+  rollback: bool,
+}
+
+impl From<deployment::Model> for DeploymentData {
+  fn from(deployment: deployment::Model) -> Self {
+    Self {
+      id: deployment.id,
+      old_commit_id: deployment.old_commit_id.map(hex::encode),
+      new_commit_id: hex::encode(deployment.new_commit_id),
+      created: deployment.created,
+      actor: deployment.actor,
+      rollback: deployment.rollback,
+    }
+  }
+}
+
+pub(crate) async fn record(
+  tx: &DatabaseTransaction,
+  environment_id: Uuid,
+  old_commit_id: Option<Vec<u8>>,
+  new_commit_id: Vec<u8>,
+  actor: &Actor,
+  rollback: bool,
+) -> Result<(), Error> {
+  let deployment = deployment::ActiveModel {
+    id: Set(Uuid::new_v4()),
+    sequence: NotSet,
+    environment_id: Set(environment_id),
+    old_commit_id: Set(old_commit_id),
+    new_commit_id: Set(new_commit_id),
+    created: Set(OffsetDateTime::now_utc()),
+    actor: Set(actor.name.clone()),
+    rollback: Set(rollback),
+  };
+
+  deployment.insert(tx).await?;
+
+  Ok(())
+}
+
+#[debug_handler]
+pub(crate) async fn list(
+  State(state): State<ManagementState>,
+  Path(name): Path<String>,
+) -> Result<Json<Vec<DeploymentData>>, Error> {
+  let environment = find_environment(&name)
+    .one(&state.db)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  let deployments = deployment::Entity::find()
+    .filter(deployment::Column::EnvironmentId.eq(environment.id))
+    .order_by_desc(deployment::Column::Sequence)
+    .all(&state.db)
+    .await?;
+
+  Ok(Json(deployments.into_iter().map(Into::into).collect()))
+}
+
+#[debug_handler]
+pub(crate) async fn rollback(
+  State(state): State<ManagementState>,
+  Path(name): Path<String>,
+  actor: Actor,
+  Json(data): Json<RollbackData>,
+) -> Result<Json<EnvironmentData>, Error> {
+  actor.require(Scope::Publish(&name))?;
+
+  let tx = state.db.begin().await?;
+  let environment = rollback_endpoint(&tx, name, data, &actor).await?;
+  tx.commit().await?;
+
+  Ok(Json(environment.into()))
+}
+
+async fn rollback_endpoint(
+  tx: &DatabaseTransaction,
+  name: String,
+  data: RollbackData,
+  actor: &Actor,
+) -> Result<environment::Model, Error> {
+  if data.steps == 0 {
+    return Err(Error::InvalidSteps);
+  }
+
+  let environment = find_environment(&name)
+    .lock_exclusive()
+    .one(tx)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  // every deployment remembers what it replaced, so going back a step means
+  // finding the deployment that brought the current commit and restoring what
+  // it replaced. Rollbacks are skipped, otherwise rolling back twice would
+  // return to where it started.
+  let mut target = environment.commit_id.clone();
+  let mut before: Option<deployment::Model> = None;
+
+  for _ in 0..data.steps {
+    let mut condition = Condition::all()
+      .add(deployment::Column::EnvironmentId.eq(environment.id))
+      .add(deployment::Column::NewCommitId.eq(target.clone()))
+      .add(deployment::Column::Rollback.eq(false));
+
+    // only look further back than the previous step
+    if let Some(before) = &before {
+      condition = condition.add(deployment::Column::Sequence.lt(before.sequence));
+    }
+
+    let deployment = deployment::Entity::find()
+      .filter(condition)
+      .order_by_desc(deployment::Column::Sequence)
+      .one(tx)
+      .await?
+      .ok_or(Error::NoPreviousDeployment)?;
+
+    target = deployment
+      .old_commit_id
+      .clone()
+      .ok_or(Error::NoPreviousDeployment)?;
+    before = Some(deployment);
+  }
+
+  switch_commit(tx, environment, target, actor, true).await
+}
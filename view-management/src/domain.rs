@@ -0,0 +1,143 @@
+use axum::debug_handler;
+use axum::extract::State;
+use axum::http::StatusCode;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+  ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, IntoActiveModel, ModelTrait,
+  PaginatorTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use view_entity::{domain, environment};
+
+use crate::actor::{Actor, Scope};
+use crate::environment::{find_environment, lock_domain, taken, validate_domain};
+use crate::error::Error;
+use crate::extract::{Json, Path};
+use crate::ManagementState;
+
+#[derive(Deserialize)]
+pub(crate) struct PutDomainData {
+  #[serde(default)]
+  redirect: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DomainData {
+  domain: String,
+  redirect: bool,
+}
+
+impl From<domain::Model> for DomainData {
+  fn from(domain: domain::Model) -> Self {
+    Self {
+      domain: domain.name,
+      redirect: domain.redirect,
+    }
+  }
+}
+
+#[debug_handler]
+pub(crate) async fn list(
+  State(state): State<ManagementState>,
+  Path(name): Path<String>,
+) -> Result<Json<Vec<DomainData>>, Error> {
+  let environment = find_environment(&name)
+    .one(&state.db)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  let domains = environment
+    .find_related(domain::Entity)
+    .order_by_asc(domain::Column::Name)
+    .all(&state.db)
+    .await?;
+
+  Ok(Json(domains.into_iter().map(Into::into).collect()))
+}
+
+#[debug_handler]
+pub(crate) async fn put(
+  State(state): State<ManagementState>,
+  Path((name, domain)): Path<(String, String)>,
+  actor: Actor,
+  Json(data): Json<PutDomainData>,
+) -> Result<Json<DomainData>, Error> {
+  actor.require(Scope::Admin)?;
+
+  let tx = state.db.begin().await?;
+  let domain = put_endpoint(&tx, name, domain, data).await?;
+  tx.commit().await?;
+
+  Ok(Json(domain.into()))
+}
+
+async fn put_endpoint(
+  tx: &DatabaseTransaction,
+  name: String,
+  domain: String,
+  data: PutDomainData,
+) -> Result<domain::Model, Error> {
+  let environment = find_environment(&name)
+    .one(tx)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  let domain = validate_domain(domain)?;
+
+  lock_domain(tx, &domain).await?;
+
+  let primary = environment::Entity::find()
+    .filter(environment::Column::Domain.eq(domain.as_str()))
+    .count(tx)
+    .await?
+    > 0;
+
+  if primary {
+    return Err(Error::DomainTaken);
+  }
+
+  match domain::Entity::find_by_id(domain.clone()).one(tx).await? {
+    Some(existing) if existing.environment_id != environment.id => Err(Error::DomainTaken),
+    Some(existing) => {
+      let mut existing = existing.into_active_model();
+      existing.redirect = Set(data.redirect);
+      Ok(existing.update(tx).await?)
+    }
+    None => {
+      let domain = domain::ActiveModel {
+        name: Set(domain),
+        environment_id: Set(environment.id),
+        redirect: Set(data.redirect),
+      };
+
+      domain.insert(tx).await.map_err(taken)
+    }
+  }
+}
+
+#[debug_handler]
+pub(crate) async fn delete(
+  State(state): State<ManagementState>,
+  Path((name, domain)): Path<(String, String)>,
+  actor: Actor,
+) -> Result<StatusCode, Error> {
+  actor.require(Scope::Admin)?;
+
+  let environment = find_environment(&name)
+    .one(&state.db)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  let domain = validate_domain(domain)?;
+
+  let domain = domain::Entity::find_by_id(domain)
+    .filter(domain::Column::EnvironmentId.eq(environment.id))
+    .one(&state.db)
+    .await?
+    .ok_or(Error::DomainNotFound)?;
+
+  domain.delete(&state.db).await?;
+
+  Ok(StatusCode::NO_CONTENT)
+}
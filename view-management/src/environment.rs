@@ -0,0 +1,504 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{debug_handler, Extension};
+use hex::FromHex;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+  ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseTransaction, DbErr,
+  EntityTrait, IntoActiveModel, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+  RuntimeErr, Select, Statement, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use view_entity::commit::CommitStatus;
+use view_entity::environment::TrailingSlash;
+use view_entity::{commit, domain, environment};
+
+use crate::actor::{Actor, Scope};
+use crate::audit::AuditTarget;
+use crate::deployment;
+use crate::error::Error;
+use crate::extract::{Json, Path};
+use crate::ManagementState;
+
+pub(crate) fn find_environment(name: &str) -> Select<environment::Entity> {
+  environment::Entity::find().filter(environment::Column::Name.eq(name))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CreateEnvironmentData {
+  name: String,
+  domain: String,
+  commit_id: String,
+  #[serde(default)]
+  trailing_slash: TrailingSlashData,
+  #[serde(default)]
+  clean_urls: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UpdateEnvironmentData {
+  name: Option<String>,
+  domain: Option<String>,
+  trailing_slash: Option<TrailingSlashData>,
+  clean_urls: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TrailingSlashData {
+  #[default]
+  Preserve,
+  Always,
+  Never,
+}
+
+impl From<TrailingSlashData> for TrailingSlash {
+  fn from(data: TrailingSlashData) -> Self {
+    match data {
+      TrailingSlashData::Preserve => TrailingSlash::Preserve,
+      TrailingSlashData::Always => TrailingSlash::Always,
+      TrailingSlashData::Never => TrailingSlash::Never,
+    }
+  }
+}
+
+impl From<TrailingSlash> for TrailingSlashData {
+  fn from(trailing_slash: TrailingSlash) -> Self {
+    match trailing_slash {
+      TrailingSlash::Preserve => TrailingSlashData::Preserve,
+      TrailingSlash::Always => TrailingSlashData::Always,
+      TrailingSlash::Never => TrailingSlashData::Never,
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PublishData {
+  commit_id: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct EnvironmentData {
+  id: Uuid,
+  name: String,
+  domain: String,
+  commit_id: String,
+  trailing_slash: TrailingSlashData,
+  clean_urls: bool,
+}
+
+impl From<environment::Model> for EnvironmentData {
+  fn from(environment: environment::Model) -> Self {
+    Self {
+      id: environment.id,
+      name: environment.name,
+      domain: environment.domain,
+      commit_id: hex::encode(environment.commit_id),
+      trailing_slash: environment.trailing_slash.into(),
+      clean_urls: environment.clean_urls,
+    }
+  }
+}
+
+#[debug_handler]
+pub(crate) async fn list(
+  State(state): State<ManagementState>,
+) -> Result<Json<Vec<EnvironmentData>>, Error> {
+  let environments = environment::Entity::find()
+    .order_by_asc(environment::Column::Name)
+    .all(&state.db)
+    .await?;
+
+  Ok(Json(environments.into_iter().map(Into::into).collect()))
+}
+
+#[debug_handler]
+pub(crate) async fn get(
+  State(state): State<ManagementState>,
+  Path(name): Path<String>,
+) -> Result<Json<EnvironmentData>, Error> {
+  let environment = find_environment(&name)
+    .one(&state.db)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  Ok(Json(environment.into()))
+}
+
+#[debug_handler]
+pub(crate) async fn create(
+  State(state): State<ManagementState>,
+  actor: Actor,
+  Json(data): Json<CreateEnvironmentData>,
+) -> Result<(StatusCode, Extension<AuditTarget>, Json<EnvironmentData>), Error> {
+  actor.require(Scope::Admin)?;
+
+  let tx = state.db.begin().await?;
+  let environment = create_endpoint(&tx, data, &actor).await?;
+  tx.commit().await?;
+
+  Ok((
+    StatusCode::CREATED,
+    Extension(AuditTarget(environment.name.clone())),
+    Json(environment.into()),
+  ))
+}
+
+async fn create_endpoint(
+  tx: &DatabaseTransaction,
+  data: CreateEnvironmentData,
+  actor: &Actor,
+) -> Result<environment::Model, Error> {
+  let name = validate_name(data.name)?;
+  let domain = validate_domain(data.domain)?;
+  let commit_id = <[u8; 20]>::from_hex(&data.commit_id).map_err(|_| Error::InvalidCommitId)?;
+
+  ensure_unique(tx, None, Some(&name), Some(&domain)).await?;
+
+  ensure_complete(tx, &commit_id).await?;
+
+  let environment = environment::ActiveModel {
+    id: Set(Uuid::new_v4()),
+    name: Set(name),
+    domain: Set(domain),
+    commit_id: Set(commit_id.to_vec()),
+    trailing_slash: Set(data.trailing_slash.into()),
+    clean_urls: Set(data.clean_urls),
+  };
+
+  let environment = environment.insert(tx).await.map_err(taken)?;
+  deployment::record(
+    tx,
+    environment.id,
+    None,
+    environment.commit_id.clone(),
+    actor,
+    false,
+  )
+  .await?;
+
+  Ok(environment)
+}
+
+#[debug_handler]
+pub(crate) async fn update(
+  State(state): State<ManagementState>,
+  Path(name): Path<String>,
+  actor: Actor,
+  Json(data): Json<UpdateEnvironmentData>,
+) -> Result<Json<EnvironmentData>, Error> {
+  actor.require(Scope::Admin)?;
+
+  let tx = state.db.begin().await?;
+  let environment = update_endpoint(&tx, name, data).await?;
+  tx.commit().await?;
+
+  Ok(Json(environment.into()))
+}
+
+async fn update_endpoint(
+  tx: &DatabaseTransaction,
+  current_name: String,
+  data: UpdateEnvironmentData,
+) -> Result<environment::Model, Error> {
+  let environment = find_environment(&current_name)
+    .one(tx)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  let name = data.name.map(validate_name).transpose()?;
+  let domain = data.domain.map(validate_domain).transpose()?;
+
+  ensure_unique(tx, Some(environment.id), name.as_deref(), domain.as_deref()).await?;
+
+  let mut environment = environment.into_active_model();
+
+  if let Some(name) = name {
+    environment.name = Set(name);
+  }
+
+  if let Some(domain) = domain {
+    environment.domain = Set(domain);
+  }
+
+  if let Some(trailing_slash) = data.trailing_slash {
+    environment.trailing_slash = Set(trailing_slash.into());
+  }
+
+  if let Some(clean_urls) = data.clean_urls {
+    environment.clean_urls = Set(clean_urls);
+  }
+
+  environment.update(tx).await.map_err(taken)
+}
+
+#[debug_handler]
+pub(crate) async fn publish(
+  State(state): State<ManagementState>,
+  Path(name): Path<String>,
+  actor: Actor,
+  Json(data): Json<PublishData>,
+) -> Result<Json<EnvironmentData>, Error> {
+  actor.require(Scope::Publish(&name))?;
+
+  let tx = state.db.begin().await?;
+  let environment = publish_endpoint(&tx, name, data, &actor).await?;
+  tx.commit().await?;
+
+  Ok(Json(environment.into()))
+}
+
+async fn publish_endpoint(
+  tx: &DatabaseTransaction,
+  name: String,
+  data: PublishData,
+  actor: &Actor,
+) -> Result<environment::Model, Error> {
+  let commit_id = <[u8; 20]>::from_hex(&data.commit_id).map_err(|_| Error::InvalidCommitId)?;
+
+  // lock the row, so concurrent publishes of the same environment are serialized
+  let environment = find_environment(&name)
+    .lock_exclusive()
+    .one(tx)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  switch_commit(tx, environment, commit_id.to_vec(), actor, false).await
+}
+
+/// Points the environment at another commit and records the switch in its
+/// deployment history.
+pub(crate) async fn switch_commit(
+  tx: &DatabaseTransaction,
+  environment: environment::Model,
+  commit_id: Vec<u8>,
+  actor: &Actor,
+  rollback: bool,
+) -> Result<environment::Model, Error> {
+  ensure_complete(tx, &commit_id).await?;
+
+  let old_commit_id = environment.commit_id.clone();
+
+  let mut environment = environment.into_active_model();
+  environment.commit_id = Set(commit_id.clone());
+  let environment = environment.update(tx).await?;
+
+  deployment::record(
+    tx,
+    environment.id,
+    Some(old_commit_id),
+    commit_id,
+    actor,
+    rollback,
+  )
+  .await?;
+
+  Ok(environment)
+}
+
+/// Only commits with all objects uploaded can be served.
+async fn ensure_complete(tx: &DatabaseTransaction, commit_id: &[u8]) -> Result<(), Error> {
+  let commit = commit::Entity::find_by_id(commit_id.to_vec())
+    .one(tx)
+    .await?
+    .ok_or(Error::CommitNotFound)?;
+
+  if commit.status != CommitStatus::Complete {
+    return Err(Error::CommitIncomplete);
+  }
+
+  Ok(())
+}
+
+#[debug_handler]
+pub(crate) async fn delete(
+  State(state): State<ManagementState>,
+  Path(name): Path<String>,
+  actor: Actor,
+) -> Result<StatusCode, Error> {
+  actor.require(Scope::Admin)?;
+
+  let environment = find_environment(&name)
+    .one(&state.db)
+    .await?
+    .ok_or(Error::EnvironmentNotFound)?;
+
+  environment.delete(&state.db).await?;
+
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Checks that no other environment uses the name and no environment or alias
+/// the domain. Writes racing past the check are caught by the unique
+/// constraints, see [`taken`].
+async fn ensure_unique(
+  tx: &DatabaseTransaction,
+  exclude: Option<Uuid>,
+  name: Option<&str>,
+  domain: Option<&str>,
+) -> Result<(), Error> {
+  let others = match exclude {
+    Some(id) => Condition::all().add(environment::Column::Id.ne(id)),
+    None => Condition::all(),
+  };
+
+  if let Some(name) = name {
+    let taken = environment::Entity::find()
+      .filter(others.clone().add(environment::Column::Name.eq(name)))
+      .count(tx)
+      .await?
+      > 0;
+
+    if taken {
+      return Err(Error::NameTaken);
+    }
+  }
+
+  if let Some(domain) = domain {
+    lock_domain(tx, domain).await?;
+
+    let taken = environment::Entity::find()
+      .filter(others.add(environment::Column::Domain.eq(domain)))
+      .count(tx)
+      .await?
+      > 0
+      || domain::Entity::find_by_id(domain.to_string())
+        .count(tx)
+        .await?
+        > 0;
+
+    if taken {
+      return Err(Error::DomainTaken);
+    }
+  }
+
+  Ok(())
+}
+
+/// Serializes transactions checking and claiming the domain until they end,
+/// no unique constraint spans both environments and aliases.
+pub(crate) async fn lock_domain(tx: &DatabaseTransaction, domain: &str) -> Result<(), DbErr> {
+  tx.execute(Statement::from_sql_and_values(
+    tx.get_database_backend(),
+    "SELECT pg_advisory_xact_lock(hashtext($1))",
+    [domain.into()],
+  ))
+  .await?;
+
+  Ok(())
+}
+
+/// Maps violations of the unique constraints on names and domains, left by
+/// concurrent requests claiming the same one.
+pub(crate) fn taken(err: DbErr) -> Error {
+  let db_err = match &err {
+    DbErr::Exec(RuntimeErr::SqlxError(sqlx_err))
+    | DbErr::Query(RuntimeErr::SqlxError(sqlx_err)) => sqlx_err.as_database_error(),
+    _ => None,
+  };
+
+  // unique_violation
+  match db_err {
+    Some(db_err) if db_err.code().as_deref() == Some("23505") => match db_err.constraint() {
+      Some("UQ_environment_name") => Error::NameTaken,
+      _ => Error::DomainTaken,
+    },
+    _ => err.into(),
+  }
+}
+
+pub(crate) fn validate_name(name: String) -> Result<String, Error> {
+  let valid = !name.is_empty()
+    && name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+  if !valid {
+    return Err(Error::InvalidName);
+  }
+
+  Ok(name)
+}
+
+pub(crate) fn validate_domain(domain: String) -> Result<String, Error> {
+  // the host header is matched exactly, so store the form browsers send
+  let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+
+  // a leading `*.` matches any single label
+  let host = domain.strip_prefix("*.").unwrap_or(&domain);
+
+  if host.len() > 253 || !host.split('.').all(valid_label) {
+    return Err(Error::InvalidDomain);
+  }
+
+  Ok(domain)
+}
+
+/// Labels have 1 to 63 letters, digits and hyphens, but neither start nor end
+/// with a hyphen.
+fn valid_label(label: &str) -> bool {
+  (1..=63).contains(&label.len())
+    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    && !label.starts_with('-')
+    && !label.ends_with('-')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn valid(domain: &str) -> Option<String> {
+    validate_domain(domain.to_string()).ok()
+  }
+
+  #[test]
+  fn normalizes_domains() {
+    assert_eq!(valid("Example.COM").as_deref(), Some("example.com"));
+    assert_eq!(valid(" example.com. ").as_deref(), Some("example.com"));
+    assert_eq!(valid("localhost").as_deref(), Some("localhost"));
+    assert_eq!(
+      valid("xn--bcher-kva.example").as_deref(),
+      Some("xn--bcher-kva.example")
+    );
+    assert_eq!(valid("*.example.com").as_deref(), Some("*.example.com"));
+  }
+
+  #[test]
+  fn rejects_malformed_labels() {
+    for domain in [
+      "",
+      ".",
+      "example..com",
+      ".example.com",
+      "-example.com",
+      "example-.com",
+      "www.-example.com",
+      "exa_mple.com",
+      "exa mple.com",
+      "example.com:8080",
+      "bücher.example",
+    ] {
+      assert_eq!(valid(domain), None, "{}", domain);
+    }
+
+    assert!(valid(&format!("{}.com", "a".repeat(63))).is_some());
+    assert_eq!(valid(&format!("{}.com", "a".repeat(64))), None);
+    assert_eq!(valid(&["a"; 128].join(".")), None);
+  }
+
+  #[test]
+  fn rejects_malformed_wildcards() {
+    for domain in [
+      "*",
+      "*.",
+      "*.*.example.com",
+      "www.*.example.com",
+      "*example.com",
+      "*.-a.com",
+    ] {
+      assert_eq!(valid(domain), None, "{}", domain);
+    }
+  }
+}
@@ -0,0 +1,252 @@
+use axum::extract::multipart::{MultipartError, MultipartRejection};
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use sea_orm::DbErr;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+#[derive(Debug)]
+pub(crate) enum Error {
+  InvalidCommitId,
+  InvalidObjectId,
+  InvalidName,
+  InvalidDomain,
+  InvalidSteps,
+  InvalidTokenName,
+  InvalidScope,
+  InvalidExpiry,
+  InvalidPageSize,
+  /// The request body could not be parsed, with the status axum chose.
+  InvalidBody(StatusCode, String),
+  InvalidPath(StatusCode, String),
+  InvalidQuery(String),
+  InvalidUpload(String),
+  MissingUpload,
+  UploadTooLarge,
+  InvalidRule {
+    rule: &'static str,
+    index: usize,
+    reason: String,
+  },
+  DuplicatePath(String),
+  ContentMismatch,
+  Unauthorized,
+  Forbidden,
+  CommitNotFound,
+  ObjectNotFound,
+  EnvironmentNotFound,
+  DomainNotFound,
+  TokenNotFound,
+  CommitExists,
+  NameTaken,
+  DomainTaken,
+  CommitIncomplete,
+  NoPreviousDeployment,
+  Database(DbErr),
+  Store(anyhow::Error),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+  code: &'static str,
+  message: &'static str,
+  details: Option<Value>,
+}
+
+impl Error {
+  fn status(&self) -> StatusCode {
+    match self {
+      Error::InvalidCommitId
+      | Error::InvalidObjectId
+      | Error::InvalidName
+      | Error::InvalidDomain
+      | Error::InvalidSteps
+      | Error::InvalidTokenName
+      | Error::InvalidScope
+      | Error::InvalidExpiry
+      | Error::InvalidPageSize
+      | Error::InvalidQuery(_)
+      | Error::InvalidUpload(_)
+      | Error::MissingUpload => StatusCode::BAD_REQUEST,
+      Error::InvalidBody(status, _) | Error::InvalidPath(status, _) => *status,
+      Error::UploadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+      Error::InvalidRule { .. } | Error::DuplicatePath(_) | Error::ContentMismatch => {
+        StatusCode::UNPROCESSABLE_ENTITY
+      }
+      Error::Unauthorized => StatusCode::UNAUTHORIZED,
+      Error::Forbidden => StatusCode::FORBIDDEN,
+      Error::CommitNotFound
+      | Error::ObjectNotFound
+      | Error::EnvironmentNotFound
+      | Error::DomainNotFound
+      | Error::TokenNotFound => StatusCode::NOT_FOUND,
+      Error::CommitExists
+      | Error::NameTaken
+      | Error::DomainTaken
+      | Error::CommitIncomplete
+      | Error::NoPreviousDeployment => StatusCode::CONFLICT,
+      Error::Database(_) | Error::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+
+  fn code(&self) -> &'static str {
+    match self {
+      Error::InvalidCommitId => "invalid_commit_id",
+      Error::InvalidObjectId => "invalid_object_id",
+      Error::InvalidName => "invalid_name",
+      Error::InvalidDomain => "invalid_domain",
+      Error::InvalidSteps => "invalid_steps",
+      Error::InvalidTokenName => "invalid_token_name",
+      Error::InvalidScope => "invalid_scope",
+      Error::InvalidExpiry => "invalid_expiry",
+      Error::InvalidPageSize => "invalid_page_size",
+      Error::InvalidBody(..) => "invalid_body",
+      Error::InvalidPath(..) => "invalid_path",
+      Error::InvalidQuery(_) => "invalid_query",
+      Error::InvalidUpload(_) => "invalid_upload",
+      Error::MissingUpload => "missing_upload",
+      Error::UploadTooLarge => "upload_too_large",
+      Error::InvalidRule { .. } => "invalid_rule",
+      Error::DuplicatePath(_) => "duplicate_path",
+      Error::ContentMismatch => "content_mismatch",
+      Error::Unauthorized => "unauthorized",
+      Error::Forbidden => "forbidden",
+      Error::CommitNotFound => "commit_not_found",
+      Error::ObjectNotFound => "object_not_found",
+      Error::EnvironmentNotFound => "environment_not_found",
+      Error::DomainNotFound => "domain_not_found",
+      Error::TokenNotFound => "token_not_found",
+      Error::CommitExists => "commit_exists",
+      Error::NameTaken => "name_taken",
+      Error::DomainTaken => "domain_taken",
+      Error::CommitIncomplete => "commit_incomplete",
+      Error::NoPreviousDeployment => "no_previous_deployment",
+      Error::Database(_) | Error::Store(_) => "internal",
+    }
+  }
+
+  fn message(&self) -> &'static str {
+    match self {
+      Error::InvalidCommitId => "Expected 40 hex character commit id",
+      Error::InvalidObjectId => "Expected 64 hex character object id",
+      Error::InvalidName => "Name may only contain ascii letters, digits, '-' and '_'",
+      Error::InvalidDomain => {
+        "Domain must be a non-empty host name without port, optionally starting with '*.'"
+      }
+      Error::InvalidSteps => "Steps must be at least 1",
+      Error::InvalidTokenName => "Token name must not be empty",
+      Error::InvalidScope => {
+        "Scopes must be admin, commit:write, object:write or environment:<name>:publish"
+      }
+      Error::InvalidExpiry => "Expiry must be in the future",
+      Error::InvalidPageSize => "Page size must be between 1 and 100",
+      Error::InvalidBody(..) => "The request body is invalid",
+      Error::InvalidPath(..) => "The request path is invalid",
+      Error::InvalidQuery(_) => "The query string is invalid",
+      Error::InvalidUpload(_) => "The upload is not valid multipart/form-data",
+      Error::MissingUpload => "Expected a file to upload",
+      Error::UploadTooLarge => "The upload exceeds the size limit",
+      Error::InvalidRule { .. } => "A rule of the commit is invalid",
+      Error::DuplicatePath(_) => "The commit contains a path more than once",
+      Error::ContentMismatch => "The uploaded content does not hash to the object id",
+      Error::Unauthorized => "Missing, expired or revoked token",
+      Error::Forbidden => "The token lacks the scope for this request",
+      Error::CommitNotFound => "Commit not found",
+      Error::ObjectNotFound => "Object not found",
+      Error::EnvironmentNotFound => "Environment not found",
+      Error::DomainNotFound => "Domain not found",
+      Error::TokenNotFound => "Token not found",
+      Error::CommitExists => "The commit already exists with different files",
+      Error::NameTaken => "Another environment already uses this name",
+      Error::DomainTaken => "The domain is already used by an environment",
+      Error::CommitIncomplete => "Not all objects of the commit have been uploaded",
+      Error::NoPreviousDeployment => "The environment has no deployment that far back",
+      Error::Database(_) | Error::Store(_) => "Internal server error",
+    }
+  }
+
+  /// Specifics of the failure, so the client can point at what to fix.
+  fn details(&self) -> Option<Value> {
+    match self {
+      Error::InvalidBody(_, reason)
+      | Error::InvalidPath(_, reason)
+      | Error::InvalidQuery(reason)
+      | Error::InvalidUpload(reason) => Some(json!({ "reason": reason })),
+      Error::InvalidRule {
+        rule,
+        index,
+        reason,
+      } => Some(json!({ "rule": rule, "index": index, "reason": reason })),
+      Error::DuplicatePath(path) => Some(json!({ "path": path })),
+      _ => None,
+    }
+  }
+
+  pub(crate) fn invalid_rule(rule: &'static str, index: usize, reason: String) -> Self {
+    Error::InvalidRule {
+      rule,
+      index,
+      reason,
+    }
+  }
+}
+
+impl From<DbErr> for Error {
+  fn from(err: DbErr) -> Self {
+    Error::Database(err)
+  }
+}
+
+impl From<JsonRejection> for Error {
+  fn from(rejection: JsonRejection) -> Self {
+    Error::InvalidBody(rejection.status(), rejection.body_text())
+  }
+}
+
+impl From<PathRejection> for Error {
+  fn from(rejection: PathRejection) -> Self {
+    Error::InvalidPath(rejection.status(), rejection.body_text())
+  }
+}
+
+impl From<QueryRejection> for Error {
+  fn from(rejection: QueryRejection) -> Self {
+    Error::InvalidQuery(rejection.body_text())
+  }
+}
+
+impl From<MultipartRejection> for Error {
+  fn from(rejection: MultipartRejection) -> Self {
+    Error::InvalidUpload(rejection.body_text())
+  }
+}
+
+impl From<MultipartError> for Error {
+  fn from(err: MultipartError) -> Self {
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+      Error::UploadTooLarge
+    } else {
+      Error::InvalidUpload(err.body_text())
+    }
+  }
+}
+
+impl IntoResponse for Error {
+  fn into_response(self) -> Response {
+    match &self {
+      Error::Database(err) => eprint!("Error: {:?}", err),
+      Error::Store(err) => eprint!("Error: {:?}", err),
+      _ => {}
+    }
+
+    let body = ErrorBody {
+      code: self.code(),
+      message: self.message(),
+      details: self.details(),
+    };
+
+    (self.status(), Json(body)).into_response()
+  }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashSet;
+
+use futures_util::StreamExt;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+  ActiveModelTrait, DatabaseConnection, EntityTrait, IntoActiveModel, ModelTrait, QuerySelect,
+  TransactionTrait,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use view_entity::{object, object_variant};
+use view_store::{Encoding, ObjectStore};
+
+use crate::compress;
+
+/// Ids of the objects and blobs with problems, grouped by kind of problem.
+#[derive(Serialize, Default)]
+pub struct FsckReport {
+  /// Objects without size and content, they still need to be uploaded.
+  pub not_uploaded: Vec<String>,
+  /// Objects with valid content that were never marked as uploaded.
+  pub unrecorded: Vec<String>,
+  /// Objects marked as uploaded whose content is gone.
+  pub missing: Vec<String>,
+  /// Objects whose content does not hash to their id.
+  pub corrupted: Vec<String>,
+  /// Objects whose recorded size differs from their content.
+  pub size_mismatch: Vec<String>,
+  /// Content without an object.
+  pub orphaned: Vec<String>,
+  /// Variants without a row, e.g. left behind by an upload that failed
+  /// after compressing, as `<id>.<encoding>`.
+  pub orphaned_variants: Vec<String>,
+  /// Variants with a row whose content is gone.
+  pub missing_variants: Vec<String>,
+  /// Compressible objects without variants, e.g. uploaded before variants
+  /// were made or first used under a path of another type. They are served
+  /// uncompressed, which is no inconsistency.
+  pub uncompressed: Vec<String>,
+}
+
+impl FsckReport {
+  pub fn is_clean(&self) -> bool {
+    self.unrecorded.is_empty()
+      && self.missing.is_empty()
+      && self.corrupted.is_empty()
+      && self.size_mismatch.is_empty()
+      && self.orphaned.is_empty()
+      && self.orphaned_variants.is_empty()
+      && self.missing_variants.is_empty()
+  }
+}
+
+/// Compares the objects in the database with the content in the store. With
+/// `repair` orphaned and corrupted content is deleted, sizes are corrected
+/// and objects without valid content are reset, so they get uploaded again.
+/// Variants are compared with their rows the same way and made for the
+/// objects lacking them. Repairs wait for
+/// uploads of the same object, see [`repair_object`].
+pub async fn check_consistency(
+  db: &DatabaseConnection,
+  store: &dyn ObjectStore,
+  repair: bool,
+) -> anyhow::Result<FsckReport> {
+  let mut report = FsckReport::default();
+
+  let mut blobs = store.list().await?.into_iter().collect::<HashSet<_>>();
+  let compressed = object_variant::Entity::find()
+    .all(db)
+    .await?
+    .into_iter()
+    .map(|variant| variant.object_id)
+    .collect::<HashSet<_>>();
+
+  for object in object::Entity::find().all(db).await? {
+    let id = hex::encode(&object.id);
+
+    if !blobs.remove(&object.id) {
+      match object.size {
+        Some(_) => {
+          report.missing.push(id);
+          if repair {
+            repair_object(db, store, &object.id).await?;
+          }
+        }
+        None => report.not_uploaded.push(id),
+      }
+      continue;
+    }
+
+    let problem = match (verify(store, &object.id).await?, object.size) {
+      (None, _) => &mut report.corrupted,
+      (Some(_), None) => &mut report.unrecorded,
+      (Some(size), Some(recorded)) if recorded != size as i64 => &mut report.size_mismatch,
+      (Some(size), Some(_)) => {
+        if !compressed.contains(&object.id)
+          && compress::needs_variants(db, &object.id, size).await?
+        {
+          report.uncompressed.push(id);
+          if repair {
+            compress::store_variants(db, store, &object.id, size).await?;
+          }
+        }
+        continue;
+      }
+    };
+    problem.push(id);
+
+    if repair {
+      repair_object(db, store, &object.id).await?;
+    }
+  }
+
+  for id in blobs {
+    report.orphaned.push(hex::encode(&id));
+    if repair {
+      repair_object(db, store, &id).await?;
+    }
+  }
+
+  // listed after repairing the objects, deleting their content removed the
+  // variants as well
+  let mut variants = store
+    .list_variants()
+    .await?
+    .into_iter()
+    .collect::<HashSet<_>>();
+
+  for variant in object_variant::Entity::find().all(db).await? {
+    let Some(encoding) = Encoding::from_name(&variant.encoding) else {
+      continue;
+    };
+
+    if !variants.remove(&(variant.object_id.clone(), encoding)) {
+      report
+        .missing_variants
+        .push(variant_name(&variant.object_id, encoding));
+      if repair {
+        repair_variant(db, store, &variant.object_id, encoding).await?;
+      }
+    }
+  }
+
+  for (id, encoding) in variants {
+    report.orphaned_variants.push(variant_name(&id, encoding));
+    if repair {
+      repair_variant(db, store, &id, encoding).await?;
+    }
+  }
+
+  Ok(report)
+}
+
+/// Repairs a single object while holding the lock an upload takes on its
+/// row, so content that is being uploaded right now is left alone. The state
+/// may have changed since it was checked, so it is checked again.
+async fn repair_object(
+  db: &DatabaseConnection,
+  store: &dyn ObjectStore,
+  id: &[u8],
+) -> anyhow::Result<()> {
+  let tx = db.begin().await?;
+
+  let object = object::Entity::find_by_id(id.to_vec())
+    .lock_exclusive()
+    .one(&tx)
+    .await?;
+
+  // without a row there is no upload that could be writing the content
+  let Some(object) = object else {
+    store.delete(id).await?;
+    return Ok(tx.commit().await?);
+  };
+
+  match verify(store, id).await? {
+    Some(size) if object.size != Some(size as i64) => {
+      let mut object = object.into_active_model();
+      object.size = Set(Some(size as i64));
+      object.update(&tx).await?;
+    }
+    Some(_) => {}
+    None => {
+      if store.exists(id).await? {
+        store.delete(id).await?;
+      }
+
+      if object.size.is_some() {
+        let mut object = object.into_active_model();
+        object.size = Set(None);
+        object.update(&tx).await?;
+      }
+    }
+  }
+
+  Ok(tx.commit().await?)
+}
+
+/// Deletes a variant without row or a row without variant. Variants are
+/// stored before their row is committed, so the lock on the object is taken
+/// first, like for [`repair_object`].
+async fn repair_variant(
+  db: &DatabaseConnection,
+  store: &dyn ObjectStore,
+  id: &[u8],
+  encoding: Encoding,
+) -> anyhow::Result<()> {
+  let tx = db.begin().await?;
+
+  object::Entity::find_by_id(id.to_vec())
+    .lock_exclusive()
+    .one(&tx)
+    .await?;
+
+  let row = object_variant::Entity::find_by_id((id.to_vec(), encoding.name().to_string()))
+    .one(&tx)
+    .await?;
+  let stored = store.get_variant(id, encoding).await?.is_some();
+
+  match row {
+    Some(row) if !stored => {
+      row.delete(&tx).await?;
+    }
+    None if stored => store.delete_variant(id, encoding).await?,
+    _ => {}
+  }
+
+  Ok(tx.commit().await?)
+}
+
+fn variant_name(id: &[u8], encoding: Encoding) -> String {
+  format!("{}.{}", hex::encode(id), encoding.name())
+}
+
+/// Returns the size of the content or `None` if it does not hash to the id.
+async fn verify(store: &dyn ObjectStore, id: &[u8]) -> anyhow::Result<Option<u64>> {
+  let mut stream = match store.get(id, None).await? {
+    Some(stream) => stream,
+    None => return Ok(None),
+  };
+
+  let mut size = 0;
+  let mut hasher = Sha256::new();
+
+  while let Some(chunk) = stream.next().await {
+    let chunk = chunk?;
+    size += chunk.len() as u64;
+    hasher.update(&chunk);
+  }
+
+  if hasher.finalize().as_slice() != id {
+    return Ok(None);
+  }
+
+  Ok(Some(size))
+}
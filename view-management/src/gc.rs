@@ -0,0 +1,217 @@
+use std::collections::{HashMap, HashSet};
+
+use sea_orm::sea_query::{Alias, Query, SelectStatement};
+use sea_orm::{
+  ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DatabaseTransaction, EntityTrait,
+  PaginatorTrait, QueryFilter, QueryOrder, Statement, TransactionTrait,
+};
+use time::{Duration, OffsetDateTime};
+
+use view_entity::commit::CommitStatus;
+use view_entity::{commit, deployment, environment, file, object};
+use view_store::ObjectStore;
+
+/// Keeps the number of ids in a single statement well below the parameter
+/// limit of the database.
+const CHUNK_SIZE: usize = 1000;
+
+/// Commits matching any of the rules are kept, together with the commits
+/// environments currently point at and pending commits, whose deploy is still
+/// uploading.
+pub struct RetentionPolicy {
+  /// Number of most recently deployed commits to keep per environment.
+  pub keep_commits: usize,
+  /// Commits created within this many days are kept.
+  pub keep_days: u32,
+}
+
+#[derive(Default)]
+pub struct GcReport {
+  pub commits: usize,
+  pub files: u64,
+  pub objects: usize,
+  pub bytes: u64,
+}
+
+/// Deletes commits outside of the retention policy together with their
+/// files, and afterwards every object no file refers to anymore. With
+/// `dry_run` only reports what would be deleted.
+pub async fn collect_garbage(
+  db: &DatabaseConnection,
+  store: &dyn ObjectStore,
+  policy: &RetentionPolicy,
+  dry_run: bool,
+) -> anyhow::Result<GcReport> {
+  let tx = db.begin().await?;
+
+  let mut keep = HashSet::new();
+
+  for environment in environment::Entity::find().all(&tx).await? {
+    keep.insert(environment.commit_id);
+  }
+
+  let deployments = deployment::Entity::find()
+    .order_by_desc(deployment::Column::Sequence)
+    .all(&tx)
+    .await?;
+
+  let mut deployed = HashMap::<_, Vec<Vec<u8>>>::new();
+  for deployment in deployments {
+    let commits = deployed.entry(deployment.environment_id).or_default();
+    if commits.len() < policy.keep_commits && !commits.contains(&deployment.new_commit_id) {
+      commits.push(deployment.new_commit_id);
+    }
+  }
+  keep.extend(deployed.into_values().flatten());
+
+  let cutoff = OffsetDateTime::now_utc() - Duration::days(policy.keep_days.into());
+  let recent = commit::Entity::find()
+    .filter(
+      Condition::any()
+        .add(commit::Column::Created.gt(cutoff))
+        .add(commit::Column::Status.eq(CommitStatus::Pending)),
+    )
+    .all(&tx)
+    .await?;
+  keep.extend(recent.into_iter().map(|commit| commit.id));
+
+  let keep = keep.into_iter().collect::<Vec<_>>();
+  store_kept(&tx, &keep).await?;
+
+  let commits = commit::Entity::find()
+    .filter(commit::Column::Id.not_in_subquery(kept()))
+    .all(&tx)
+    .await?
+    .into_iter()
+    .map(|commit| commit.id)
+    .collect::<Vec<_>>();
+
+  let files = file::Entity::find()
+    .filter(file::Column::CommitId.not_in_subquery(kept()))
+    .count(&tx)
+    .await?;
+
+  let objects = object::Entity::find()
+    .filter(
+      object::Column::Id.not_in_subquery(
+        Query::select()
+          .column(file::Column::ObjectId)
+          .from(file::Entity)
+          .and_where(file::Column::CommitId.in_subquery(kept()))
+          .to_owned(),
+      ),
+    )
+    .all(&tx)
+    .await?;
+
+  let report = GcReport {
+    commits: commits.len(),
+    files,
+    objects: objects.len(),
+    bytes: objects
+      .iter()
+      .filter_map(|object| object.size)
+      .map(|size| size as u64)
+      .sum(),
+  };
+
+  if dry_run {
+    return Ok(report);
+  }
+
+  for chunk in commits.chunks(CHUNK_SIZE) {
+    deployment::Entity::delete_many()
+      .filter(
+        Condition::any()
+          .add(deployment::Column::NewCommitId.is_in(chunk.to_vec()))
+          .add(deployment::Column::OldCommitId.is_in(chunk.to_vec())),
+      )
+      .exec(&tx)
+      .await?;
+
+    file::Entity::delete_many()
+      .filter(file::Column::CommitId.is_in(chunk.to_vec()))
+      .exec(&tx)
+      .await?;
+
+    commit::Entity::delete_many()
+      .filter(commit::Column::Id.is_in(chunk.to_vec()))
+      .exec(&tx)
+      .await?;
+  }
+
+  tx.commit().await?;
+
+  for object in objects {
+    delete_object(db, store, &object.id).await?;
+  }
+
+  Ok(report)
+}
+
+/// Deletes the object and its blob, unless a commit started using it again
+/// in the meantime. The row stays locked until the blob is gone, so a commit
+/// adding the object again waits and gets it uploaded anew. If deleting the
+/// blob fails, the row is kept and the next run tries again.
+async fn delete_object(
+  db: &DatabaseConnection,
+  store: &dyn ObjectStore,
+  id: &[u8],
+) -> anyhow::Result<()> {
+  let tx = db.begin().await?;
+
+  let deleted = object::Entity::delete_many()
+    .filter(object::Column::Id.eq(id.to_vec()))
+    .filter(
+      object::Column::Id.not_in_subquery(
+        Query::select()
+          .column(file::Column::ObjectId)
+          .from(file::Entity)
+          .and_where(file::Column::ObjectId.eq(id.to_vec()))
+          .to_owned(),
+      ),
+    )
+    .exec(&tx)
+    .await?;
+
+  if deleted.rows_affected > 0 {
+    store.delete(id).await?;
+  }
+
+  Ok(tx.commit().await?)
+}
+
+/// The kept commits can be far more than fit into a single statement, so
+/// they are inserted into a temporary table in chunks and referred to by
+/// [`kept`]. The table is dropped with the transaction.
+async fn store_kept(tx: &DatabaseTransaction, keep: &[Vec<u8>]) -> Result<(), sea_orm::DbErr> {
+  let backend = tx.get_database_backend();
+
+  tx.execute(Statement::from_string(
+    backend,
+    "CREATE TEMPORARY TABLE gc_keep (id bytea PRIMARY KEY) ON COMMIT DROP".to_string(),
+  ))
+  .await?;
+
+  for chunk in keep.chunks(CHUNK_SIZE) {
+    let mut insert = Query::insert();
+    insert
+      .into_table(Alias::new("gc_keep"))
+      .columns([Alias::new("id")]);
+
+    for id in chunk {
+      insert.values_panic([id.clone().into()]);
+    }
+
+    tx.execute(backend.build(&insert)).await?;
+  }
+
+  Ok(())
+}
+
+fn kept() -> SelectStatement {
+  Query::select()
+    .column(Alias::new("id"))
+    .from(Alias::new("gc_keep"))
+    .to_owned()
+}
@@ -1,49 +1,124 @@
+use std::collections::HashSet;
+use std::io;
 use std::iter::once;
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
-use anyhow::anyhow;
 use axum::body::Body;
-use axum::extract::{Multipart, Path, State};
+use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
+use axum::extract::multipart::{MultipartError, MultipartRejection};
+use axum::extract::{Multipart, State};
 use axum::http::header::AUTHORIZATION;
 use axum::http::StatusCode;
-use axum::routing::{put, IntoMakeService};
-use axum::{debug_handler, Json, Router};
+use axum::middleware::from_fn_with_state;
+use axum::routing::{delete, get, post, put};
+use axum::{debug_handler, Router};
+use futures_util::{StreamExt, TryStreamExt};
 use hex::FromHex;
 use hex_buffer_serde::{ConstHex, ConstHexForm};
+use sea_orm::sea_query::OnConflict;
 use sea_orm::ActiveValue::Set;
 use sea_orm::{
-  ActiveModelTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel, NotSet,
-  PaginatorTrait, TransactionTrait,
+  ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DatabaseTransaction, EntityTrait,
+  IntoActiveModel, JoinType, NotSet, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+  RelationTrait, Select, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use time::OffsetDateTime;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
 use tower_http::sensitive_headers::SetSensitiveRequestHeadersLayer;
-use tower_http::validate_request::ValidateRequestHeaderLayer;
+use tracing::error;
 
+use view_entity::commit::CommitStatus;
 use view_entity::{commit, file, object};
+use view_store::ObjectStore;
+
+use crate::actor::{authenticate, Actor, Authenticator, Scope};
+use crate::error::Error;
+use crate::extract::{Json, Path};
+
+use crate::rules::{
+  insert_error_pages, insert_header_rules, insert_redirect_rules, ErrorPageData, HeaderRuleData,
+  RedirectRuleData,
+};
+
+mod actor;
+mod audit;
+mod compress;
+mod deployment;
+mod domain;
+mod environment;
+mod error;
+mod extract;
+mod fsck;
+mod gc;
+mod rules;
+mod token;
+
+pub use fsck::{check_consistency, FsckReport};
+pub use gc::{collect_garbage, GcReport, RetentionPolicy};
 
 #[derive(Clone)]
 pub struct ManagementState {
   pub db: DatabaseConnection,
-  pub root_dir: PathBuf,
+  pub store: Arc<dyn ObjectStore>,
+  /// Commits are served at `<commit id prefix>.<preview domain>`.
+  pub preview_domain: Option<String>,
 }
 
-pub fn router(state: ManagementState, token: &str) -> IntoMakeService<Router<(), Body>> {
+/// The static `token` is granted every scope, it is meant to create the
+/// scoped tokens for everything else.
+pub fn router(
+  state: ManagementState,
+  token: &str,
+) -> IntoMakeServiceWithConnectInfo<Router<(), Body>, SocketAddr> {
+  let authenticator = Authenticator {
+    db: state.db.clone(),
+    bootstrap_hash: Sha256::digest(token.as_bytes()).into(),
+  };
+
   Router::new()
-    .route("/v1/commit/:id", put(commit))
+    .route("/v1/commit/:id", get(get_commit).put(commit))
+    .route("/v1/commit/:id/finalize", post(finalize))
     .route("/v1/object/:id", put(object))
-    .layer(ValidateRequestHeaderLayer::bearer(token))
+    .route(
+      "/v1/environment",
+      get(environment::list).post(environment::create),
+    )
+    .route(
+      "/v1/environment/:name",
+      get(environment::get)
+        .patch(environment::update)
+        .delete(environment::delete),
+    )
+    .route("/v1/environment/:name/publish", post(environment::publish))
+    .route("/v1/environment/:name/rollback", post(deployment::rollback))
+    .route("/v1/environment/:name/deployment", get(deployment::list))
+    .route("/v1/environment/:name/domain", get(domain::list))
+    .route(
+      "/v1/environment/:name/domain/:domain",
+      put(domain::put).delete(domain::delete),
+    )
+    .route("/v1/token", get(token::list).post(token::create))
+    .route("/v1/token/:id", delete(token::revoke))
+    .route("/v1/audit", get(audit::list))
+    .route_layer(from_fn_with_state(state.db.clone(), audit::record))
+    .layer(from_fn_with_state(authenticator, authenticate))
     .layer(SetSensitiveRequestHeadersLayer::new(once(AUTHORIZATION)))
     .with_state(state)
-    .into_make_service()
+    .into_make_service_with_connect_info::<SocketAddr>()
 }
 
 #[derive(Deserialize, Clone)]
 struct CommitData {
   description: String,
   files: Vec<FileData>,
+  #[serde(default)]
+  headers: Vec<HeaderRuleData>,
+  #[serde(default)]
+  redirects: Vec<RedirectRuleData>,
+  #[serde(default)]
+  error_pages: Vec<ErrorPageData>,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -54,68 +129,230 @@ struct FileData {
   fallback: bool,
 }
 
+#[derive(Serialize)]
+struct CommitInfoData {
+  id: String,
+  description: String,
+  #[serde(with = "time::serde::rfc3339")]
+  created: OffsetDateTime,
+  status: CommitStatusData,
+  preview_url: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CommitStatusData {
+  Pending,
+  Complete,
+  Failed,
+}
+
+impl From<CommitStatus> for CommitStatusData {
+  fn from(status: CommitStatus) -> Self {
+    match status {
+      CommitStatus::Pending => CommitStatusData::Pending,
+      CommitStatus::Complete => CommitStatusData::Complete,
+      CommitStatus::Failed => CommitStatusData::Failed,
+    }
+  }
+}
+
+impl CommitInfoData {
+  fn new(commit: commit::Model, preview_domain: Option<&str>) -> Self {
+    let id = hex::encode(commit.id);
+
+    // long enough to not be ambiguous, view-serve accepts any prefix of at
+    // least 7 characters, but only serves complete commits
+    let preview_url = preview_domain
+      .filter(|_| commit.status == CommitStatus::Complete)
+      .map(|domain| format!("https://{}.{}/", &id[..12], domain));
+
+    Self {
+      id,
+      description: commit.description,
+      created: commit.created,
+      status: commit.status.into(),
+      preview_url,
+    }
+  }
+}
+
+#[debug_handler]
+async fn get_commit(
+  State(state): State<ManagementState>,
+  Path(id): Path<String>,
+) -> Result<Json<CommitInfoData>, Error> {
+  let id = <[u8; 20]>::from_hex(&id).map_err(|_| Error::InvalidCommitId)?;
+
+  let commit = commit::Entity::find_by_id(id.to_vec())
+    .one(&state.db)
+    .await?
+    .ok_or(Error::CommitNotFound)?;
+
+  Ok(Json(CommitInfoData::new(
+    commit,
+    state.preview_domain.as_deref(),
+  )))
+}
+
 #[debug_handler]
 async fn commit(
   State(state): State<ManagementState>,
   Path(id): Path<String>,
+  actor: Actor,
   Json(commit): Json<CommitData>,
-) -> Result<Json<Vec<FileData>>, StatusCode> {
-  let result = match state.db.begin().await {
-    Ok(tx) => match commit_endpoint(&tx, id, commit).await {
-      Ok(result) => {
-        tx.commit().await.unwrap();
-        Ok(result)
-      }
-      Err(err) => Err(err),
-    },
-    Err(err) => Err::<_, anyhow::Error>(err.into()),
-  };
+) -> Result<Json<Vec<FileData>>, Error> {
+  actor.require(Scope::CommitWrite)?;
 
-  match result {
-    Ok(result) => Ok(Json(result)),
-    Err(err) => {
-      eprint!("Error: {:?}", err);
-      Err(StatusCode::INTERNAL_SERVER_ERROR)
-    }
-  }
+  let tx = state.db.begin().await?;
+  let result = commit_endpoint(&tx, id, commit).await?;
+  tx.commit().await?;
+
+  Ok(Json(result))
 }
 
+/// Creating a commit is idempotent, so a CI job can be re-run for the same
+/// revision. Every file whose object has no content yet is returned, no matter
+/// which commit created the object.
 async fn commit_endpoint(
   tx: &DatabaseTransaction,
   input_id: String,
   commit_data: CommitData,
-) -> anyhow::Result<Vec<FileData>> {
-  let id = <[u8; 20]>::from_hex(input_id)?;
+) -> Result<Vec<FileData>, Error> {
+  let id = <[u8; 20]>::from_hex(input_id).map_err(|_| Error::InvalidCommitId)?;
+
+  let mut paths = HashSet::new();
+  for file in &commit_data.files {
+    if !paths.insert(file.path.as_str()) {
+      return Err(Error::DuplicatePath(file.path.clone()));
+    }
+  }
+
+  if !insert_commit(tx, &id, &commit_data).await? {
+    ensure_same_files(tx, &id, &commit_data.files).await?;
+  }
+
+  let missing = find_missing_files(&id)
+    .order_by_asc(file::Column::Path)
+    .all(tx)
+    .await?;
+
+  // the same content may be used by several paths, it is uploaded once
+  let mut object_ids = HashSet::new();
+  let mut objects_to_upload = Vec::new();
+
+  for file in missing {
+    if object_ids.insert(file.object_id.clone()) {
+      objects_to_upload.push(FileData {
+        path: file.path,
+        object_id: file
+          .object_id
+          .try_into()
+          .map_err(|_| Error::InvalidObjectId)?,
+        fallback: file.fallback,
+      });
+    }
+  }
 
+  let complete = commit::Entity::find_by_id(id.to_vec())
+    .one(tx)
+    .await?
+    .is_some_and(|commit| commit.status == CommitStatus::Complete);
+
+  // a complete commit may be served already, it stays complete while the
+  // objects that went missing, e.g. reset by fsck, are uploaded again
+  if objects_to_upload.is_empty() {
+    set_status(tx, &id, CommitStatus::Complete).await?;
+  } else if !complete {
+    set_status(tx, &id, CommitStatus::Pending).await?;
+  }
+
+  Ok(objects_to_upload)
+}
+
+/// Files of the commit whose object has no content yet.
+fn find_missing_files(commit_id: &[u8]) -> Select<file::Entity> {
+  file::Entity::find()
+    .join(JoinType::InnerJoin, file::Relation::Object.def())
+    .filter(
+      Condition::all()
+        .add(file::Column::CommitId.eq(commit_id.to_vec()))
+        .add(object::Column::Size.is_null()),
+    )
+}
+
+async fn set_status(
+  tx: &DatabaseTransaction,
+  commit_id: &[u8],
+  status: CommitStatus,
+) -> Result<(), Error> {
+  let commit = commit::ActiveModel {
+    id: Set(commit_id.to_vec()),
+    status: Set(status),
+    ..Default::default()
+  };
+
+  commit.update(tx).await?;
+
+  Ok(())
+}
+
+/// Returns `false` if the commit exists already. An insert of the same commit
+/// by a concurrent request is waited for, so its files can be compared.
+async fn insert_commit(
+  tx: &DatabaseTransaction,
+  id: &[u8; 20],
+  commit_data: &CommitData,
+) -> Result<bool, Error> {
   let commit = commit::ActiveModel {
     id: Set(id.to_vec()),
-    description: Set(commit_data.description),
+    description: Set(commit_data.description.clone()),
     created: Set(OffsetDateTime::now_utc()),
+    status: Set(CommitStatus::Pending),
   };
 
-  commit::Entity::insert(commit).exec(tx).await?;
+  let inserted = commit::Entity::insert(commit)
+    .on_conflict(
+      OnConflict::column(commit::Column::Id)
+        .do_nothing()
+        .to_owned(),
+    )
+    .exec_without_returning(tx)
+    .await?;
 
-  let mut objects_to_upload = Vec::new();
+  if inserted == 0 {
+    return Ok(false);
+  }
 
-  for file in commit_data.files {
-    let object_is_new = object::Entity::find_by_id(file.object_id.to_vec())
-      .count(tx)
-      .await?
-      == 0;
-
-    if object_is_new {
-      let object = object::ActiveModel {
-        id: Set(file.object_id.to_vec()),
-        size: NotSet,
-        created: Set(OffsetDateTime::now_utc()),
-      };
-
-      object::Entity::insert(object).exec(tx).await?;
-      objects_to_upload.push(file.clone());
-    }
+  insert_header_rules(tx, id, commit_data.headers.clone()).await?;
+  insert_redirect_rules(tx, id, commit_data.redirects.clone()).await?;
+
+  let paths = commit_data
+    .files
+    .iter()
+    .map(|file| file.path.as_str())
+    .collect::<Vec<_>>();
+  insert_error_pages(tx, id, &paths, commit_data.error_pages.clone()).await?;
+
+  for file in &commit_data.files {
+    // other commits may add the same object at the same time
+    let object = object::ActiveModel {
+      id: Set(file.object_id.to_vec()),
+      size: NotSet,
+      created: Set(OffsetDateTime::now_utc()),
+    };
+
+    object::Entity::insert(object)
+      .on_conflict(
+        OnConflict::column(object::Column::Id)
+          .do_nothing()
+          .to_owned(),
+      )
+      .exec_without_returning(tx)
+      .await?;
 
     let file = file::ActiveModel {
-      path: Set(file.path),
+      path: Set(file.path.clone()),
       object_id: Set(file.object_id.to_vec()),
       commit_id: Set(id.to_vec()),
       fallback: Set(file.fallback),
@@ -124,79 +361,193 @@ async fn commit_endpoint(
     file::Entity::insert(file).exec(tx).await?;
   }
 
-  Ok(objects_to_upload)
+  Ok(true)
+}
+
+/// A re-submitted commit has to describe the same files, otherwise the
+/// commit id is reused for different content.
+async fn ensure_same_files(
+  tx: &DatabaseTransaction,
+  id: &[u8; 20],
+  files: &[FileData],
+) -> Result<(), Error> {
+  let existing = file::Entity::find()
+    .filter(file::Column::CommitId.eq(id.to_vec()))
+    .all(tx)
+    .await?
+    .into_iter()
+    .map(|file| (file.path, file.object_id, file.fallback))
+    .collect::<HashSet<_>>();
+
+  let submitted = files
+    .iter()
+    .map(|file| (file.path.clone(), file.object_id.to_vec(), file.fallback))
+    .collect::<HashSet<_>>();
+
+  if existing != submitted {
+    return Err(Error::CommitExists);
+  }
+
+  Ok(())
 }
 
 #[debug_handler]
 async fn object(
   State(state): State<ManagementState>,
   Path(id): Path<String>,
-  multipart: Multipart,
-) -> Result<(), StatusCode> {
-  let result = match state.db.begin().await {
-    Ok(tx) => {
-      match object_endpoint(&tx, state.root_dir, id.to_ascii_lowercase(), multipart).await {
-        Ok(_) => {
-          tx.commit().await.unwrap();
-          Ok(())
-        }
-        Err(err) => Err(err),
+  actor: Actor,
+  multipart: Result<Multipart, MultipartRejection>,
+) -> Result<StatusCode, Error> {
+  actor.require(Scope::ObjectWrite)?;
+  let multipart = multipart?;
+
+  let tx = state.db.begin().await?;
+  let (id, size) = object_endpoint(&tx, &*state.store, id.to_ascii_lowercase(), multipart).await?;
+  tx.commit().await?;
+
+  // compressing with the best settings takes a while, the variants are
+  // stored once they are done
+  tokio::spawn(async move {
+    let stored = async {
+      if compress::needs_variants(&state.db, &id, size).await? {
+        compress::store_variants(&state.db, &*state.store, &id, size).await?;
       }
-    }
-    Err(err) => Err::<_, anyhow::Error>(err.into()),
-  };
+      anyhow::Ok(())
+    };
 
-  match result {
-    Ok(_) => Ok(()),
-    Err(err) => {
-      eprint!("Error: {:?}", err);
-      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    if let Err(err) = stored.await {
+      error!("Compressing object {} failed: {:?}", hex::encode(id), err);
     }
-  }
+  });
+
+  Ok(StatusCode::OK)
 }
 
+/// Stores the uploaded content of an object. If the content does not hash to
+/// the object id, neither the object nor any previously stored content is
+/// touched, so the upload can be retried.
 async fn object_endpoint(
   tx: &DatabaseTransaction,
-  root_dir: PathBuf,
+  store: &dyn ObjectStore,
   input_id: String,
   mut multipart: Multipart,
-) -> anyhow::Result<()> {
-  if input_id.len() != 64 {
-    return Err(anyhow!("Expected 64 hex character commit id"));
-  }
-
-  let id = <[u8; 32]>::from_hex(&input_id)?;
+) -> Result<([u8; 32], u64), Error> {
+  let id = <[u8; 32]>::from_hex(&input_id).map_err(|_| Error::InvalidObjectId)?;
 
-  let mut object = match object::Entity::find_by_id(id).one(tx).await? {
+  // held until the upload is committed, fsck takes it before repairing
+  let object = object::Entity::find_by_id(id)
+    .lock_exclusive()
+    .one(tx)
+    .await?;
+  let mut object = match object {
     Some(object) => object.into_active_model(),
-    None => return Err(anyhow!("object not found")),
+    None => return Err(Error::ObjectNotFound),
   };
 
-  let path = root_dir.join(&input_id[0..2]).join(&input_id[2..]);
-  if let Some(parent) = path.parent() {
-    if !tokio::fs::try_exists(parent).await? {
-      tokio::fs::create_dir(parent).await?;
+  let field = multipart.next_field().await?.ok_or(Error::MissingUpload)?;
+
+  let stream = field.map_err(io::Error::other).boxed();
+
+  let size = match store.put(&id, stream).await {
+    Ok(Some(size)) => size,
+    Ok(None) => return Err(Error::ContentMismatch),
+    Err(err) => return Err(upload_error(err)),
+  };
+
+  object.size = Set(Some(size as i64));
+  object.update(tx).await?;
+
+  complete_commits(tx, &id).await?;
+
+  Ok((id, size))
+}
+
+/// Marks the pending commits using the object as complete once none of their
+/// objects is missing. Uploads running in parallel do not see each other, so
+/// the last one may leave the commit pending, finalizing catches up on that.
+async fn complete_commits(tx: &DatabaseTransaction, object_id: &[u8]) -> Result<(), Error> {
+  let commits = commit::Entity::find()
+    .join(JoinType::InnerJoin, commit::Relation::File.def())
+    .filter(
+      Condition::all()
+        .add(file::Column::ObjectId.eq(object_id.to_vec()))
+        .add(commit::Column::Status.eq(CommitStatus::Pending)),
+    )
+    .distinct()
+    .all(tx)
+    .await?;
+
+  for commit in commits {
+    if find_missing_files(&commit.id).count(tx).await? == 0 {
+      set_status(tx, &commit.id, CommitStatus::Complete).await?;
     }
   }
 
-  let mut size = 0;
+  Ok(())
+}
 
-  {
-    let mut file = File::create(path).await?;
+#[debug_handler]
+async fn finalize(
+  State(state): State<ManagementState>,
+  Path(id): Path<String>,
+  actor: Actor,
+) -> Result<Json<CommitInfoData>, Error> {
+  actor.require(Scope::CommitWrite)?;
 
-    let mut field = multipart
-      .next_field()
-      .await?
-      .ok_or_else(|| anyhow!("Expected file to upload"))?;
+  let tx = state.db.begin().await?;
+  let commit = finalize_endpoint(&tx, id).await?;
+  tx.commit().await?;
 
-    while let Some(chunk) = field.chunk().await? {
-      size += chunk.len();
-      file.write_all(chunk.as_ref()).await?;
-    }
+  // the failed status is kept, so it is reported after committing
+  if commit.status != CommitStatus::Complete {
+    return Err(Error::CommitIncomplete);
   }
 
-  object.size = Set(Some(size as i64));
-  object.update(tx).await?;
+  Ok(Json(CommitInfoData::new(
+    commit,
+    state.preview_domain.as_deref(),
+  )))
+}
 
-  Ok(())
+/// Called once all uploads are done, the commit is complete if no object is
+/// missing and failed otherwise. Re-submitting a failed commit makes it
+/// pending again, complete commits stay complete.
+async fn finalize_endpoint(
+  tx: &DatabaseTransaction,
+  input_id: String,
+) -> Result<commit::Model, Error> {
+  let id = <[u8; 20]>::from_hex(input_id).map_err(|_| Error::InvalidCommitId)?;
+
+  let commit = commit::Entity::find_by_id(id.to_vec())
+    .lock_exclusive()
+    .one(tx)
+    .await?
+    .ok_or(Error::CommitNotFound)?;
+
+  let status = match find_missing_files(&commit.id).count(tx).await? {
+    0 => CommitStatus::Complete,
+    _ if commit.status == CommitStatus::Complete => return Err(Error::CommitIncomplete),
+    _ => CommitStatus::Failed,
+  };
+
+  let mut commit = commit.into_active_model();
+  commit.status = Set(status);
+
+  Ok(commit.update(tx).await?)
+}
+
+/// The store reads the upload while writing it, so errors of the client
+/// surface as store errors.
+fn upload_error(err: anyhow::Error) -> Error {
+  let multipart = err
+    .chain()
+    .find_map(|cause| cause.downcast_ref::<io::Error>())
+    .and_then(|err| err.get_ref())
+    .and_then(|inner| inner.downcast_ref::<MultipartError>());
+
+  match multipart {
+    Some(multipart) if multipart.status() == StatusCode::PAYLOAD_TOO_LARGE => Error::UploadTooLarge,
+    Some(multipart) => Error::InvalidUpload(multipart.body_text()),
+    None => Error::Store(err),
+  }
 }
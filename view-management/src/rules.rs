@@ -0,0 +1,213 @@
+use axum::http::header::{
+  HeaderName, HeaderValue, CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, ETAG,
+  TRANSFER_ENCODING,
+};
+use std::collections::BTreeMap;
+
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseTransaction, EntityTrait};
+use serde::Deserialize;
+
+use view_entity::{error_page, header_rule, redirect_rule};
+
+use crate::environment::validate_domain;
+use crate::error::Error;
+
+/// Headers that describe the representation and framing of the content,
+/// which is up to view-serve alone.
+const RESERVED_HEADERS: [HeaderName; 6] = [
+  CONNECTION,
+  CONTENT_ENCODING,
+  CONTENT_LENGTH,
+  CONTENT_RANGE,
+  ETAG,
+  TRANSFER_ENCODING,
+];
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct HeaderRuleData {
+  path: String,
+  name: String,
+  value: String,
+}
+
+/// 200 rewrites the request to another path, the rest redirects.
+const REDIRECT_STATUSES: [u16; 5] = [200, 301, 302, 307, 308];
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct RedirectRuleData {
+  source: String,
+  destination: String,
+  status: u16,
+  #[serde(default)]
+  query: BTreeMap<String, String>,
+  host: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct ErrorPageData {
+  status: u16,
+  path: String,
+}
+
+pub(crate) async fn insert_header_rules(
+  tx: &DatabaseTransaction,
+  commit_id: &[u8],
+  rules: Vec<HeaderRuleData>,
+) -> Result<(), Error> {
+  for (position, rule) in rules.into_iter().enumerate() {
+    let invalid = |reason: String| Error::invalid_rule("header", position, reason);
+
+    if !rule.path.starts_with('/') {
+      return Err(invalid(format!(
+        "Header rule path {} must start with /",
+        rule.path
+      )));
+    }
+
+    let name = HeaderName::from_bytes(rule.name.as_bytes())
+      .map_err(|_| invalid(format!("Invalid header name {}", rule.name)))?;
+
+    if RESERVED_HEADERS.contains(&name) {
+      return Err(invalid(format!("Header {} can not be set by rules", name)));
+    }
+
+    HeaderValue::from_str(&rule.value)
+      .map_err(|_| invalid(format!("Invalid value for header {}", name)))?;
+
+    let rule = header_rule::ActiveModel {
+      commit_id: Set(commit_id.to_vec()),
+      position: Set(position as i32),
+      path: Set(rule.path),
+      // header names are case-insensitive, store them the way they are sent
+      name: Set(name.as_str().to_string()),
+      value: Set(rule.value),
+    };
+
+    header_rule::Entity::insert(rule).exec(tx).await?;
+  }
+
+  Ok(())
+}
+
+pub(crate) async fn insert_redirect_rules(
+  tx: &DatabaseTransaction,
+  commit_id: &[u8],
+  rules: Vec<RedirectRuleData>,
+) -> Result<(), Error> {
+  for (position, rule) in rules.into_iter().enumerate() {
+    let invalid = |reason: String| Error::invalid_rule("redirect", position, reason);
+
+    if !rule.source.starts_with('/') {
+      return Err(invalid(format!(
+        "Redirect source {} must start with /",
+        rule.source
+      )));
+    }
+
+    let splat_valid = match rule.source.find('*') {
+      Some(idx) => idx == rule.source.len() - 1 && rule.source.ends_with("/*"),
+      None => true,
+    };
+
+    if !splat_valid {
+      return Err(invalid(format!(
+        "Redirect source {} may only end with a /* splat",
+        rule.source
+      )));
+    }
+
+    if !REDIRECT_STATUSES.contains(&rule.status) {
+      return Err(invalid(format!(
+        "Unsupported redirect status {}",
+        rule.status
+      )));
+    }
+
+    // rewrites are served from the same commit
+    if rule.status == 200 && !rule.destination.starts_with('/') {
+      return Err(invalid(format!(
+        "Rewrite destination {} must start with /",
+        rule.destination
+      )));
+    }
+
+    if rule.destination.is_empty() || HeaderValue::from_str(&rule.destination).is_err() {
+      return Err(invalid(format!(
+        "Invalid redirect destination {}",
+        rule.destination
+      )));
+    }
+
+    let mut query = Vec::with_capacity(rule.query.len());
+    for (key, value) in rule.query {
+      if key.is_empty() || key.contains(['&', '=']) || value.contains('&') {
+        return Err(invalid(format!(
+          "Invalid query condition {}={}",
+          key, value
+        )));
+      }
+
+      query.push(format!("{}={}", key, value));
+    }
+
+    // rules are matched against the exact host, wildcards would never match
+    let host = rule
+      .host
+      .map(|host| match validate_domain(host.clone()) {
+        Ok(domain) if !domain.starts_with("*.") => Ok(domain),
+        _ => Err(invalid(format!("Invalid host {}", host))),
+      })
+      .transpose()?;
+
+    let rule = redirect_rule::ActiveModel {
+      commit_id: Set(commit_id.to_vec()),
+      position: Set(position as i32),
+      source: Set(rule.source),
+      destination: Set(rule.destination),
+      status: Set(rule.status as i16),
+      query: Set((!query.is_empty()).then(|| query.join("&"))),
+      host: Set(host),
+    };
+
+    redirect_rule::Entity::insert(rule).exec(tx).await?;
+  }
+
+  Ok(())
+}
+
+/// Stores the error pages of the commit, `paths` are the paths of its files.
+pub(crate) async fn insert_error_pages(
+  tx: &DatabaseTransaction,
+  commit_id: &[u8],
+  paths: &[&str],
+  pages: Vec<ErrorPageData>,
+) -> Result<(), Error> {
+  for (index, page) in pages.into_iter().enumerate() {
+    let invalid = |reason: String| Error::invalid_rule("error_page", index, reason);
+
+    if page.status != 404 && !(500..=599).contains(&page.status) {
+      return Err(invalid(format!(
+        "Error pages are only supported for 404 and 5xx, not {}",
+        page.status
+      )));
+    }
+
+    if !paths.contains(&page.path.as_str()) {
+      return Err(invalid(format!(
+        "Error page {} is not part of the commit",
+        page.path
+      )));
+    }
+
+    let page = error_page::ActiveModel {
+      commit_id: Set(commit_id.to_vec()),
+      status: Set(page.status as i16),
+      path: Set(page.path),
+    };
+
+    error_page::Entity::insert(page).exec(tx).await?;
+  }
+
+  Ok(())
+}
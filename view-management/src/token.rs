@@ -0,0 +1,163 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{debug_handler, Extension};
+use rand::RngCore;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveModelTrait, EntityTrait, IntoActiveModel, QueryOrder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use view_entity::token;
+
+use crate::actor::{publish_scope, Actor, Scope};
+use crate::audit::AuditTarget;
+use crate::environment::validate_name;
+use crate::error::Error;
+use crate::extract::{Json, Path};
+use crate::ManagementState;
+
+#[derive(Deserialize)]
+pub(crate) struct CreateTokenData {
+  name: String,
+  scopes: Vec<String>,
+  #[serde(default, with = "time::serde::rfc3339::option")]
+  expires: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct TokenData {
+  id: Uuid,
+  name: String,
+  scopes: Vec<String>,
+  #[serde(with = "time::serde::rfc3339")]
+  created: OffsetDateTime,
+  #[serde(with = "time::serde::rfc3339::option")]
+  last_used: Option<OffsetDateTime>,
+  #[serde(with = "time::serde::rfc3339::option")]
+  expires: Option<OffsetDateTime>,
+  #[serde(with = "time::serde::rfc3339::option")]
+  revoked: Option<OffsetDateTime>,
+}
+
+impl From<token::Model> for TokenData {
+  fn from(token: token::Model) -> Self {
+    Self {
+      id: token.id,
+      name: token.name,
+      scopes: token
+        .scopes
+        .split_whitespace()
+        .map(str::to_string)
+        .collect(),
+      created: token.created,
+      last_used: token.last_used,
+      expires: token.expires,
+      revoked: token.revoked,
+    }
+  }
+}
+
+/// A new token together with its secret, which is not stored and can not be
+/// retrieved later.
+#[derive(Serialize)]
+pub(crate) struct CreatedTokenData {
+  #[serde(flatten)]
+  token: TokenData,
+  secret: String,
+}
+
+#[debug_handler]
+pub(crate) async fn list(
+  State(state): State<ManagementState>,
+  actor: Actor,
+) -> Result<Json<Vec<TokenData>>, Error> {
+  actor.require(Scope::Admin)?;
+
+  let tokens = token::Entity::find()
+    .order_by_asc(token::Column::Created)
+    .all(&state.db)
+    .await?;
+
+  Ok(Json(tokens.into_iter().map(Into::into).collect()))
+}
+
+#[debug_handler]
+pub(crate) async fn create(
+  State(state): State<ManagementState>,
+  actor: Actor,
+  Json(data): Json<CreateTokenData>,
+) -> Result<(StatusCode, Extension<AuditTarget>, Json<CreatedTokenData>), Error> {
+  actor.require(Scope::Admin)?;
+
+  let name = data.name.trim().to_string();
+  if name.is_empty() {
+    return Err(Error::InvalidTokenName);
+  }
+
+  if data.scopes.is_empty() || !data.scopes.iter().all(|scope| valid_scope(scope)) {
+    return Err(Error::InvalidScope);
+  }
+
+  let now = OffsetDateTime::now_utc();
+  if data.expires.is_some_and(|expires| expires <= now) {
+    return Err(Error::InvalidExpiry);
+  }
+
+  let mut bytes = [0u8; 32];
+  rand::thread_rng().fill_bytes(&mut bytes);
+  let secret = format!("view_{}", hex::encode(bytes));
+
+  let token = token::ActiveModel {
+    id: Set(Uuid::new_v4()),
+    name: Set(name),
+    secret_hash: Set(Sha256::digest(secret.as_bytes()).to_vec()),
+    scopes: Set(data.scopes.join(" ")),
+    created: Set(now),
+    last_used: Set(None),
+    expires: Set(data.expires),
+    revoked: Set(None),
+  };
+
+  let token = token.insert(&state.db).await?;
+
+  Ok((
+    StatusCode::CREATED,
+    Extension(AuditTarget(token.id.to_string())),
+    Json(CreatedTokenData {
+      token: token.into(),
+      secret,
+    }),
+  ))
+}
+
+#[debug_handler]
+pub(crate) async fn revoke(
+  State(state): State<ManagementState>,
+  Path(id): Path<Uuid>,
+  actor: Actor,
+) -> Result<StatusCode, Error> {
+  actor.require(Scope::Admin)?;
+
+  let token = token::Entity::find_by_id(id)
+    .one(&state.db)
+    .await?
+    .ok_or(Error::TokenNotFound)?;
+
+  if token.revoked.is_none() {
+    let mut token = token.into_active_model();
+    token.revoked = Set(Some(OffsetDateTime::now_utc()));
+    token.update(&state.db).await?;
+  }
+
+  Ok(StatusCode::NO_CONTENT)
+}
+
+fn valid_scope(scope: &str) -> bool {
+  match scope {
+    "admin" | "commit:write" | "object:write" => true,
+    _ => publish_scope(scope)
+      .is_some_and(|name| name == "*" || validate_name(name.to_string()).is_ok()),
+  }
+}
@@ -2,12 +2,36 @@ use sea_orm_migration::async_trait::async_trait;
 use sea_orm_migration::{MigrationTrait, MigratorTrait};
 
 mod m20220101_000001_init;
+mod m20230520_000002_environment_name;
+mod m20230520_000003_deployment;
+mod m20230521_000004_object_variant;
+mod m20230522_000005_header_rule;
+mod m20230522_000006_redirect_rule;
+mod m20230523_000007_environment_paths;
+mod m20230523_000008_error_page;
+mod m20230524_000009_domain;
+mod m20230525_000010_token;
+mod m20230525_000011_audit;
+mod m20230526_000012_commit_status;
 
 pub struct Migrator;
 
 #[async_trait]
 impl MigratorTrait for Migrator {
   fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-    vec![Box::new(m20220101_000001_init::Migration)]
+    vec![
+      Box::new(m20220101_000001_init::Migration),
+      Box::new(m20230520_000002_environment_name::Migration),
+      Box::new(m20230520_000003_deployment::Migration),
+      Box::new(m20230521_000004_object_variant::Migration),
+      Box::new(m20230522_000005_header_rule::Migration),
+      Box::new(m20230522_000006_redirect_rule::Migration),
+      Box::new(m20230523_000007_environment_paths::Migration),
+      Box::new(m20230523_000008_error_page::Migration),
+      Box::new(m20230524_000009_domain::Migration),
+      Box::new(m20230525_000010_token::Migration),
+      Box::new(m20230525_000011_audit::Migration),
+      Box::new(m20230526_000012_commit_status::Migration),
+    ]
   }
 }
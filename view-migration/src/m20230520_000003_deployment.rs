@@ -0,0 +1,109 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Deployment::Table)
+          .col(
+            ColumnDef::new(Deployment::Id)
+              .uuid()
+              .not_null()
+              .primary_key(),
+          )
+          .col(
+            ColumnDef::new(Deployment::Sequence)
+              .big_integer()
+              .not_null()
+              .auto_increment(),
+          )
+          .col(ColumnDef::new(Deployment::EnvironmentId).uuid().not_null())
+          .col(ColumnDef::new(Deployment::OldCommitId).binary_len(20))
+          .col(
+            ColumnDef::new(Deployment::NewCommitId)
+              .binary_len(20)
+              .not_null(),
+          )
+          .col(
+            ColumnDef::new(Deployment::Created)
+              .timestamp_with_time_zone()
+              .not_null(),
+          )
+          .col(ColumnDef::new(Deployment::Actor).string().not_null())
+          .col(
+            ColumnDef::new(Deployment::Rollback)
+              .boolean()
+              .not_null()
+              .default(false),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("FK_deployment_to_environment_id")
+              .from(Deployment::Table, Deployment::EnvironmentId)
+              .to(Environment::Table, Environment::Id)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("FK_deployment_to_old_commit_id")
+              .from(Deployment::Table, Deployment::OldCommitId)
+              .to(Commit::Table, Commit::Id),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("FK_deployment_to_new_commit_id")
+              .from(Deployment::Table, Deployment::NewCommitId)
+              .to(Commit::Table, Commit::Id),
+          )
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("IDX_deployment_environment_id_sequence")
+          .table(Deployment::Table)
+          .col(Deployment::EnvironmentId)
+          .col(Deployment::Sequence)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(Deployment::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum Deployment {
+  Table,
+  Id,
+  Sequence,
+  EnvironmentId,
+  OldCommitId,
+  NewCommitId,
+  Created,
+  Actor,
+  Rollback,
+}
+
+#[derive(Iden)]
+enum Environment {
+  Table,
+  Id,
+}
+
+#[derive(Iden)]
+enum Commit {
+  Table,
+  Id,
+}
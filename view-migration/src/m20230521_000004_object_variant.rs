@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(ObjectVariant::Table)
+          .col(
+            ColumnDef::new(ObjectVariant::ObjectId)
+              .binary_len(32)
+              .not_null(),
+          )
+          .col(ColumnDef::new(ObjectVariant::Encoding).string().not_null())
+          .col(
+            ColumnDef::new(ObjectVariant::Size)
+              .big_unsigned()
+              .not_null(),
+          )
+          .primary_key(
+            Index::create()
+              .col(ObjectVariant::ObjectId)
+              .col(ObjectVariant::Encoding),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("FK_object_variant_to_object_id")
+              .from(ObjectVariant::Table, ObjectVariant::ObjectId)
+              .to(Object::Table, Object::Id)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(ObjectVariant::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum ObjectVariant {
+  Table,
+  ObjectId,
+  Encoding,
+  Size,
+}
+
+#[derive(Iden)]
+enum Object {
+  Table,
+  Id,
+}
@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(HeaderRule::Table)
+          .col(
+            ColumnDef::new(HeaderRule::CommitId)
+              .binary_len(20)
+              .not_null(),
+          )
+          .col(ColumnDef::new(HeaderRule::Position).integer().not_null())
+          .col(ColumnDef::new(HeaderRule::Path).string().not_null())
+          .col(ColumnDef::new(HeaderRule::Name).string().not_null())
+          .col(ColumnDef::new(HeaderRule::Value).text().not_null())
+          .primary_key(
+            Index::create()
+              .col(HeaderRule::CommitId)
+              .col(HeaderRule::Position),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("FK_header_rule_to_commit_id")
+              .from(HeaderRule::Table, HeaderRule::CommitId)
+              .to(Commit::Table, Commit::Id)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(HeaderRule::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum HeaderRule {
+  Table,
+  CommitId,
+  Position,
+  Path,
+  Name,
+  Value,
+}
+
+#[derive(Iden)]
+enum Commit {
+  Table,
+  Id,
+}
@@ -0,0 +1,68 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(RedirectRule::Table)
+          .col(
+            ColumnDef::new(RedirectRule::CommitId)
+              .binary_len(20)
+              .not_null(),
+          )
+          .col(ColumnDef::new(RedirectRule::Position).integer().not_null())
+          .col(ColumnDef::new(RedirectRule::Source).string().not_null())
+          .col(ColumnDef::new(RedirectRule::Destination).text().not_null())
+          .col(
+            ColumnDef::new(RedirectRule::Status)
+              .small_integer()
+              .not_null(),
+          )
+          .col(ColumnDef::new(RedirectRule::Query).text())
+          .col(ColumnDef::new(RedirectRule::Host).string())
+          .primary_key(
+            Index::create()
+              .col(RedirectRule::CommitId)
+              .col(RedirectRule::Position),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("FK_redirect_rule_to_commit_id")
+              .from(RedirectRule::Table, RedirectRule::CommitId)
+              .to(Commit::Table, Commit::Id)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(RedirectRule::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum RedirectRule {
+  Table,
+  CommitId,
+  Position,
+  Source,
+  Destination,
+  Status,
+  Query,
+  Host,
+}
+
+#[derive(Iden)]
+enum Commit {
+  Table,
+  Id,
+}
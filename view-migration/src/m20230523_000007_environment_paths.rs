@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Environment::Table)
+          .add_column(
+            ColumnDef::new(Environment::TrailingSlash)
+              .string_len(16)
+              .not_null()
+              .default("preserve"),
+          )
+          .add_column(
+            ColumnDef::new(Environment::CleanUrls)
+              .boolean()
+              .not_null()
+              .default(false),
+          )
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Environment::Table)
+          .drop_column(Environment::TrailingSlash)
+          .drop_column(Environment::CleanUrls)
+          .to_owned(),
+      )
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum Environment {
+  Table,
+  TrailingSlash,
+  CleanUrls,
+}
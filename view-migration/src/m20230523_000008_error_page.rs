@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(ErrorPage::Table)
+          .col(
+            ColumnDef::new(ErrorPage::CommitId)
+              .binary_len(20)
+              .not_null(),
+          )
+          .col(ColumnDef::new(ErrorPage::Status).small_integer().not_null())
+          .col(ColumnDef::new(ErrorPage::Path).string().not_null())
+          .primary_key(
+            Index::create()
+              .col(ErrorPage::CommitId)
+              .col(ErrorPage::Status),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("FK_error_page_to_commit_id")
+              .from(ErrorPage::Table, ErrorPage::CommitId)
+              .to(Commit::Table, Commit::Id)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(ErrorPage::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum ErrorPage {
+  Table,
+  CommitId,
+  Status,
+  Path,
+}
+
+#[derive(Iden)]
+enum Commit {
+  Table,
+  Id,
+}
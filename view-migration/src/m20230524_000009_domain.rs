@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Domain::Table)
+          .col(
+            ColumnDef::new(Domain::Name)
+              .string()
+              .not_null()
+              .primary_key(),
+          )
+          .col(ColumnDef::new(Domain::EnvironmentId).uuid().not_null())
+          .col(
+            ColumnDef::new(Domain::Redirect)
+              .boolean()
+              .not_null()
+              .default(false),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("FK_domain_to_environment_id")
+              .from(Domain::Table, Domain::EnvironmentId)
+              .to(Environment::Table, Environment::Id)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(Domain::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum Domain {
+  Table,
+  Name,
+  EnvironmentId,
+  Redirect,
+}
+
+#[derive(Iden)]
+enum Environment {
+  Table,
+  Id,
+}
@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Token::Table)
+          .col(ColumnDef::new(Token::Id).uuid().not_null().primary_key())
+          .col(ColumnDef::new(Token::Name).string().not_null())
+          .col(
+            ColumnDef::new(Token::SecretHash)
+              .binary_len(32)
+              .unique_key()
+              .not_null(),
+          )
+          .col(ColumnDef::new(Token::Scopes).text().not_null())
+          .col(
+            ColumnDef::new(Token::Created)
+              .timestamp_with_time_zone()
+              .not_null(),
+          )
+          .col(ColumnDef::new(Token::LastUsed).timestamp_with_time_zone())
+          .col(ColumnDef::new(Token::Expires).timestamp_with_time_zone())
+          .col(ColumnDef::new(Token::Revoked).timestamp_with_time_zone())
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(Token::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum Token {
+  Table,
+  Id,
+  Name,
+  SecretHash,
+  Scopes,
+  Created,
+  LastUsed,
+  Expires,
+  Revoked,
+}
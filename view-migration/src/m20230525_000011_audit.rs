@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Audit::Table)
+          .col(ColumnDef::new(Audit::Id).uuid().not_null().primary_key())
+          .col(
+            ColumnDef::new(Audit::Created)
+              .timestamp_with_time_zone()
+              .not_null(),
+          )
+          .col(ColumnDef::new(Audit::Actor).string().not_null())
+          .col(ColumnDef::new(Audit::TokenId).uuid())
+          .col(ColumnDef::new(Audit::Action).string().not_null())
+          .col(ColumnDef::new(Audit::Target).string())
+          .col(ColumnDef::new(Audit::Method).string().not_null())
+          .col(ColumnDef::new(Audit::Path).text().not_null())
+          .col(ColumnDef::new(Audit::RemoteAddr).string())
+          .col(ColumnDef::new(Audit::UserAgent).text())
+          .col(ColumnDef::new(Audit::Status).small_integer().not_null())
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("IDX_audit_created")
+          .table(Audit::Table)
+          .col(Audit::Created)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(Audit::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum Audit {
+  Table,
+  Id,
+  Created,
+  Actor,
+  TokenId,
+  Action,
+  Target,
+  Method,
+  Path,
+  RemoteAddr,
+  UserAgent,
+  Status,
+}
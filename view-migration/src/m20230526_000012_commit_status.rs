@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Commit::Table)
+          .add_column(
+            ColumnDef::new(Commit::Status)
+              .string_len(16)
+              .not_null()
+              .default("pending"),
+          )
+          .to_owned(),
+      )
+      .await?;
+
+    // existing commits are complete unless some of their objects were never
+    // uploaded
+    let incomplete = Query::select()
+      .column((File::Table, File::CommitId))
+      .from(File::Table)
+      .inner_join(
+        Object::Table,
+        Expr::col((Object::Table, Object::Id)).equals((File::Table, File::ObjectId)),
+      )
+      .and_where(Expr::col((Object::Table, Object::Size)).is_null())
+      .to_owned();
+
+    let complete = Query::update()
+      .table(Commit::Table)
+      .value(Commit::Status, "complete")
+      .and_where(Expr::col(Commit::Id).not_in_subquery(incomplete))
+      .to_owned();
+
+    let backend = manager.get_database_backend();
+    manager
+      .get_connection()
+      .execute(backend.build(&complete))
+      .await?;
+
+    Ok(())
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Commit::Table)
+          .drop_column(Commit::Status)
+          .to_owned(),
+      )
+      .await
+  }
+}
+
+#[derive(Iden)]
+enum Commit {
+  Table,
+  Id,
+  Status,
+}
+
+#[derive(Iden)]
+enum File {
+  Table,
+  CommitId,
+  ObjectId,
+}
+
+#[derive(Iden)]
+enum Object {
+  Table,
+  Id,
+  Size,
+}
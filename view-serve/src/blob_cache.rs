@@ -0,0 +1,174 @@
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use hyper::body::Bytes;
+use lru::LruCache;
+
+use view_store::{ByteStream, Encoding, ObjectStore};
+
+type Key = (Vec<u8>, Option<Encoding>);
+
+struct Entries {
+  lru: LruCache<Key, Bytes>,
+  bytes: u64,
+}
+
+/// Reads contents from the store and keeps those of small objects and their
+/// variants in memory. Objects are shared across environments and commits, so
+/// the cache is keyed by object id. The least recently used contents are
+/// evicted once the cached bytes exceed the budget.
+pub struct BlobCache {
+  store: Arc<dyn ObjectStore>,
+  entries: Mutex<Entries>,
+  budget: u64,
+  max_object_size: u64,
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlobCacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  pub entries: usize,
+  pub bytes: u64,
+}
+
+impl BlobCache {
+  /// A budget of 0 disables the cache.
+  pub fn new(store: Arc<dyn ObjectStore>, budget: u64, max_object_size: u64) -> Self {
+    Self {
+      store,
+      entries: Mutex::new(Entries {
+        lru: LruCache::unbounded(),
+        bytes: 0,
+      }),
+      budget,
+      max_object_size: max_object_size.min(budget),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    }
+  }
+
+  pub fn stats(&self) -> BlobCacheStats {
+    let entries = self.entries.lock().unwrap();
+
+    BlobCacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      entries: entries.lru.len(),
+      bytes: entries.bytes,
+    }
+  }
+
+  /// Streams the content, or a range of it, like the store does. Contents
+  /// that fit into the cache are loaded completely on a miss.
+  pub(crate) async fn get(
+    &self,
+    id: &[u8],
+    encoding: Option<Encoding>,
+    size: Option<u64>,
+    range: Option<Range<u64>>,
+  ) -> anyhow::Result<Option<ByteStream<'static>>> {
+    let size = match size {
+      Some(size) if size <= self.max_object_size => size,
+      _ => {
+        return match encoding {
+          Some(encoding) => self.store.get_variant(id, encoding).await,
+          None => self.store.get(id, range).await,
+        };
+      }
+    };
+
+    let key = (id.to_vec(), encoding);
+
+    // the recorded size may have been corrected since the content was cached
+    let cached = self
+      .entries
+      .lock()
+      .unwrap()
+      .lru
+      .get(&key)
+      .filter(|content| content.len() as u64 == size)
+      .cloned();
+
+    let content = match cached {
+      Some(content) => {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        content
+      }
+      None => {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let stream = match encoding {
+          Some(encoding) => self.store.get_variant(id, encoding).await?,
+          None => self.store.get(id, None).await?,
+        };
+
+        let Some(stream) = stream else {
+          return Ok(None);
+        };
+
+        // stops reading once the content turns out larger than recorded, it
+        // may not fit into memory
+        let content = Bytes::from(
+          stream
+            .try_fold(Vec::new(), |mut content, chunk| async move {
+              if (content.len() + chunk.len()) as u64 > size {
+                return Err(io::Error::new(
+                  io::ErrorKind::InvalidData,
+                  "content is larger than recorded",
+                ));
+              }
+
+              content.extend_from_slice(&chunk);
+              Ok(content)
+            })
+            .await
+            .with_context(|| format!("Failed to load object {}", hex::encode(id)))?,
+        );
+
+        // ranges are checked against the recorded size, a truncated or
+        // replaced content must not be sliced
+        if content.len() as u64 != size {
+          bail!(
+            "Object {} has {} bytes instead of the recorded {}",
+            hex::encode(id),
+            content.len(),
+            size
+          );
+        }
+
+        self.insert(key, content.clone());
+        content
+      }
+    };
+
+    let content = match range {
+      Some(range) => content.slice(range.start as usize..range.end as usize),
+      None => content,
+    };
+
+    Ok(Some(stream::once(async { Ok(content) }).boxed()))
+  }
+
+  fn insert(&self, key: Key, content: Bytes) {
+    let mut entries = self.entries.lock().unwrap();
+
+    if let Some(previous) = entries.lru.put(key, content.clone()) {
+      entries.bytes -= previous.len() as u64;
+    }
+    entries.bytes += content.len() as u64;
+
+    while entries.bytes > self.budget {
+      match entries.lru.pop_lru() {
+        Some((_, evicted)) => entries.bytes -= evicted.len() as u64,
+        None => break,
+      }
+    }
+  }
+}
@@ -0,0 +1,124 @@
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+
+use view_store::Encoding;
+
+/// Objects are addressed by the SHA-256 of their content, which makes the id
+/// a strong validator. Compressed variants are different representations and
+/// need a tag of their own.
+pub(crate) fn etag(id: &[u8], encoding: Option<Encoding>) -> String {
+  match encoding {
+    Some(encoding) => format!("\"{}-{}\"", hex::encode(id), encoding.name()),
+    None => format!("\"{}\"", hex::encode(id)),
+  }
+}
+
+/// Evaluates `If-None-Match` using the weak comparison of RFC 9110.
+pub(crate) fn none_match(header: &str, etag: &str) -> bool {
+  let header = header.trim();
+  if header == "*" {
+    return false;
+  }
+
+  !header
+    .split(',')
+    .map(|tag| tag.trim())
+    .map(|tag| tag.strip_prefix("W/").unwrap_or(tag))
+    .any(|tag| tag == etag)
+}
+
+/// Evaluates `If-Modified-Since`, dates that can not be parsed are ignored as
+/// required by RFC 9110.
+pub(crate) fn modified_since(header: &str, last_modified: OffsetDateTime) -> bool {
+  match OffsetDateTime::parse(header.trim(), &Rfc2822) {
+    // http dates have no fractional seconds
+    Ok(since) => last_modified.replace_nanosecond(0).unwrap() > since,
+    Err(_) => true,
+  }
+}
+
+/// Evaluates `If-Range`, which requires a strong match of either validator.
+pub(crate) fn range_matches(header: &str, etag: &str, last_modified: &str) -> bool {
+  let header = header.trim();
+  header == etag || header == last_modified
+}
+
+#[cfg(test)]
+mod tests {
+  use time::{Date, Month, Time};
+
+  use super::*;
+
+  const ETAG: &str = "\"5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03\"";
+
+  #[test]
+  fn tags_variants_separately() {
+    let id = hex::decode(&ETAG[1..65]).unwrap();
+
+    assert_eq!(etag(&id, None), ETAG);
+    assert_eq!(
+      etag(&id, Some(Encoding::Brotli)),
+      "\"5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03-br\""
+    );
+  }
+
+  #[test]
+  fn none_match_star_matches_anything() {
+    assert!(!none_match("*", ETAG));
+    assert!(!none_match(" * ", ETAG));
+  }
+
+  #[test]
+  fn none_match_checks_every_tag() {
+    assert!(!none_match(&format!("\"a\", {}", ETAG), ETAG));
+    assert!(!none_match(&format!("\"a\",{} ,\"b\"", ETAG), ETAG));
+    assert!(none_match("\"a\", \"b\"", ETAG));
+    assert!(none_match("", ETAG));
+  }
+
+  #[test]
+  fn none_match_compares_weakly() {
+    assert!(!none_match(&format!("W/{}", ETAG), ETAG));
+    assert!(!none_match(&format!("\"a\", W/{}", ETAG), ETAG));
+    // the quotes are part of the tag
+    assert!(none_match(&ETAG[1..65], ETAG));
+  }
+
+  #[test]
+  fn modified_since_ignores_fractions_and_invalid_dates() {
+    let last_modified = Date::from_calendar_date(2023, Month::May, 20)
+      .unwrap()
+      .with_time(Time::from_hms_milli(10, 0, 0, 500).unwrap())
+      .assume_utc();
+
+    assert!(!modified_since(
+      "Sat, 20 May 2023 10:00:00 GMT",
+      last_modified
+    ));
+    assert!(!modified_since(
+      "Sat, 20 May 2023 11:00:00 GMT",
+      last_modified
+    ));
+    assert!(modified_since(
+      "Sat, 20 May 2023 09:59:59 GMT",
+      last_modified
+    ));
+    assert!(modified_since("yesterday", last_modified));
+  }
+
+  #[test]
+  fn range_requires_a_strong_match() {
+    let last_modified = "Sat, 20 May 2023 10:00:00 +0000";
+
+    assert!(range_matches(ETAG, ETAG, last_modified));
+    assert!(range_matches(&format!(" {} ", ETAG), ETAG, last_modified));
+    assert!(range_matches(last_modified, ETAG, last_modified));
+    assert!(!range_matches(&format!("W/{}", ETAG), ETAG, last_modified));
+    assert!(!range_matches("\"a\"", ETAG, last_modified));
+    assert!(!range_matches(
+      "Sat, 20 May 2023 09:00:00 +0000",
+      ETAG,
+      last_modified
+    ));
+  }
+}
@@ -0,0 +1,139 @@
+use view_store::Encoding;
+
+/// Picks the variant to send based on `Accept-Encoding`. Variants are only
+/// chosen if the client prefers them at least as much as the uncompressed
+/// content, ties go to the encoding compressing best. Returns `None` for the
+/// uncompressed content.
+pub(crate) fn negotiate(header: Option<&str>, available: &[Encoding]) -> Option<Encoding> {
+  let header = header?;
+
+  let identity = quality(header, "identity").unwrap_or(1.0);
+
+  Encoding::ALL
+    .into_iter()
+    .filter(|encoding| available.contains(encoding))
+    .map(|encoding| (encoding, quality(header, encoding.name()).unwrap_or(0.0)))
+    .filter(|(_, quality)| *quality > 0.0 && *quality >= identity)
+    // max_by would prefer the last of equal elements
+    .fold(
+      None,
+      |best: Option<(Encoding, f32)>, (encoding, quality)| match best {
+        Some((_, best_quality)) if best_quality >= quality => best,
+        _ => Some((encoding, quality)),
+      },
+    )
+    .map(|(encoding, _)| encoding)
+}
+
+/// Quality the header assigns to the coding, either explicitly or through
+/// `*`. Returns `None` if neither is listed.
+fn quality(header: &str, coding: &str) -> Option<f32> {
+  let mut wildcard = None;
+
+  for entry in header.split(',') {
+    let mut params = entry.split(';');
+    let name = params.next().unwrap_or_default().trim();
+
+    let quality = params
+      .filter_map(|param| param.trim().split_once('='))
+      .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+      .map(|(_, value)| value.trim().parse::<f32>().unwrap_or(0.0))
+      .unwrap_or(1.0);
+
+    if name.eq_ignore_ascii_case(coding)
+      || (coding == "gzip" && name.eq_ignore_ascii_case("x-gzip"))
+    {
+      return Some(quality);
+    }
+
+    if name == "*" {
+      wildcard = Some(quality);
+    }
+  }
+
+  wildcard
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ALL: &[Encoding] = &Encoding::ALL;
+
+  #[test]
+  fn needs_a_header() {
+    assert_eq!(negotiate(None, ALL), None);
+    assert_eq!(negotiate(Some(""), ALL), None);
+  }
+
+  #[test]
+  fn prefers_higher_quality() {
+    assert_eq!(negotiate(Some("gzip, br;q=0.5"), ALL), Some(Encoding::Gzip));
+    assert_eq!(
+      negotiate(
+        Some("gzip;q=0.8, zstd;q=0.9, br;q=0.1, identity;q=0.1"),
+        ALL
+      ),
+      Some(Encoding::Zstd)
+    );
+    // identity is acceptable with quality 1 unless listed otherwise
+    assert_eq!(negotiate(Some("gzip;q=0.8, zstd;q=0.9"), ALL), None);
+  }
+
+  #[test]
+  fn breaks_ties_by_compression() {
+    assert_eq!(
+      negotiate(Some("gzip, deflate, br, zstd"), ALL),
+      Some(Encoding::Brotli)
+    );
+    assert_eq!(negotiate(Some("gzip, zstd"), ALL), Some(Encoding::Zstd));
+  }
+
+  #[test]
+  fn only_picks_available_variants() {
+    assert_eq!(
+      negotiate(Some("br, gzip"), &[Encoding::Gzip]),
+      Some(Encoding::Gzip)
+    );
+    assert_eq!(negotiate(Some("br"), &[Encoding::Gzip]), None);
+    assert_eq!(negotiate(Some("br"), &[]), None);
+  }
+
+  #[test]
+  fn excludes_zero_quality() {
+    assert_eq!(negotiate(Some("br;q=0, gzip"), ALL), Some(Encoding::Gzip));
+    assert_eq!(negotiate(Some("br;q=0"), ALL), None);
+    assert_eq!(negotiate(Some("br; q=0.000"), ALL), None);
+    // qualities that can not be parsed count as 0
+    assert_eq!(negotiate(Some("br;q=high"), ALL), None);
+  }
+
+  #[test]
+  fn respects_identity() {
+    assert_eq!(negotiate(Some("gzip;q=0.5, identity"), ALL), None);
+    assert_eq!(
+      negotiate(Some("gzip;q=0.5, identity;q=0.4"), ALL),
+      Some(Encoding::Gzip)
+    );
+    assert_eq!(
+      negotiate(Some("gzip;q=0.5, identity;q=0.5"), ALL),
+      Some(Encoding::Gzip)
+    );
+  }
+
+  #[test]
+  fn applies_wildcard_to_unlisted_codings() {
+    assert_eq!(negotiate(Some("*"), ALL), Some(Encoding::Brotli));
+    assert_eq!(negotiate(Some("*, br;q=0"), ALL), Some(Encoding::Zstd));
+    assert_eq!(negotiate(Some("*;q=0.5, gzip"), ALL), Some(Encoding::Gzip));
+    // the wildcard covers identity as well
+    assert_eq!(negotiate(Some("*;q=0"), ALL), None);
+  }
+
+  #[test]
+  fn matches_codings_case_insensitively() {
+    assert_eq!(negotiate(Some("GZIP"), ALL), Some(Encoding::Gzip));
+    assert_eq!(negotiate(Some("x-gzip"), ALL), Some(Encoding::Gzip));
+    assert_eq!(negotiate(Some("BR;Q=1"), ALL), Some(Encoding::Brotli));
+  }
+}
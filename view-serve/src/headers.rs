@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE, VARY};
+use hyper::{Body, Response};
+
+use view_entity::header_rule;
+
+/// Adds the headers of all rules matching the path. The first matching rule
+/// for a header replaces the value view-serve set itself, further ones add
+/// more values. `Vary` is only ever added to, as caches must not mix up the
+/// encodings, and the type of multipart range responses is kept.
+pub(crate) fn apply_header_rules(
+  resp: &mut Response<Body>,
+  path: &str,
+  rules: &[header_rule::Model],
+) {
+  let mut replaced = HashSet::from([VARY]);
+
+  let multipart = resp
+    .headers()
+    .get(CONTENT_TYPE)
+    .is_some_and(|value| value.as_bytes().starts_with(b"multipart/byteranges"));
+
+  for rule in rules.iter().filter(|rule| glob_matches(&rule.path, path)) {
+    // rules are validated on upload
+    let (Ok(name), Ok(value)) = (
+      HeaderName::from_bytes(rule.name.as_bytes()),
+      HeaderValue::from_str(&rule.value),
+    ) else {
+      continue;
+    };
+
+    if name == CONTENT_TYPE && multipart {
+      continue;
+    }
+
+    if replaced.insert(name.clone()) {
+      resp.headers_mut().remove(&name);
+    }
+
+    resp.headers_mut().append(name, value);
+  }
+}
+
+/// Matches the path against a glob, where `*` stands for any number of
+/// characters, including `/`.
+fn glob_matches(glob: &str, path: &str) -> bool {
+  let mut parts = glob.split('*');
+
+  // without a `*` the glob has to match exactly
+  let first = parts.next().unwrap_or_default();
+  let Some(mut rest) = path.strip_prefix(first) else {
+    return false;
+  };
+
+  let parts = parts.collect::<Vec<_>>();
+  let Some((last, middle)) = parts.split_last() else {
+    return rest.is_empty();
+  };
+
+  for part in middle {
+    match rest.find(part) {
+      Some(idx) => rest = &rest[idx + part.len()..],
+      None => return false,
+    }
+  }
+
+  rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+  use hyper::StatusCode;
+
+  use super::*;
+
+  fn rule(path: &str, name: &str, value: &str) -> header_rule::Model {
+    header_rule::Model {
+      commit_id: vec![0; 20],
+      position: 0,
+      path: path.to_string(),
+      name: name.to_string(),
+      value: value.to_string(),
+    }
+  }
+
+  fn values(resp: &Response<Body>, name: HeaderName) -> Vec<&str> {
+    resp
+      .headers()
+      .get_all(name)
+      .iter()
+      .map(|value| value.to_str().unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn matches_globs_without_star_exactly() {
+    assert!(glob_matches("/index.html", "/index.html"));
+    assert!(!glob_matches("/index.html", "/index.html.br"));
+    assert!(!glob_matches("/index.html", "/docs/index.html"));
+    assert!(!glob_matches("", "/"));
+  }
+
+  #[test]
+  fn matches_stars_across_segments() {
+    assert!(glob_matches("/*", "/"));
+    assert!(glob_matches("/*", "/docs/index.html"));
+    assert!(glob_matches("/docs/*", "/docs/"));
+    assert!(glob_matches("/docs/*", "/docs/a/b.html"));
+    assert!(!glob_matches("/docs/*", "/docs"));
+    assert!(!glob_matches("/docs/*", "/blog/docs/a"));
+  }
+
+  #[test]
+  fn matches_suffixes_and_inner_stars() {
+    assert!(glob_matches("*.js", "/app.js"));
+    assert!(glob_matches("/assets/*.css", "/assets/a/b.css"));
+    assert!(!glob_matches("/assets/*.css", "/assets/a.css.map"));
+    assert!(glob_matches("/*/assets/*.js", "/en/assets/app.js"));
+    assert!(!glob_matches("/*/assets/*.js", "/en/app.js"));
+    assert!(glob_matches("/a**b", "/ab"));
+    // the parts may not overlap
+    assert!(!glob_matches("/ab*ba", "/aba"));
+  }
+
+  #[test]
+  fn replaces_then_appends() {
+    let mut resp = Response::new(Body::empty());
+    resp
+      .headers_mut()
+      .insert("cache-control", HeaderValue::from_static("no-cache"));
+
+    let rules = [
+      rule("/*", "cache-control", "public"),
+      rule("/*", "cache-control", "max-age=60"),
+      rule("/docs/*", "x-frame-options", "DENY"),
+    ];
+    apply_header_rules(&mut resp, "/index.html", &rules);
+
+    assert_eq!(
+      values(&resp, HeaderName::from_static("cache-control")),
+      ["public", "max-age=60"]
+    );
+    assert!(resp.headers().get("x-frame-options").is_none());
+  }
+
+  #[test]
+  fn adds_to_vary() {
+    let mut resp = Response::new(Body::empty());
+    resp
+      .headers_mut()
+      .insert(VARY, HeaderValue::from_static("accept-encoding"));
+
+    apply_header_rules(&mut resp, "/", &[rule("/*", "vary", "Cookie")]);
+
+    assert_eq!(values(&resp, VARY), ["accept-encoding", "Cookie"]);
+  }
+
+  #[test]
+  fn keeps_the_type_of_multipart_ranges() {
+    let rules = [rule("/*", "content-type", "text/csv")];
+
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = StatusCode::PARTIAL_CONTENT;
+    resp.headers_mut().insert(
+      CONTENT_TYPE,
+      HeaderValue::from_static("multipart/byteranges; boundary=view"),
+    );
+    apply_header_rules(&mut resp, "/data.txt", &rules);
+
+    assert_eq!(
+      values(&resp, CONTENT_TYPE),
+      ["multipart/byteranges; boundary=view"]
+    );
+
+    let mut resp = Response::new(Body::empty());
+    resp
+      .headers_mut()
+      .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    apply_header_rules(&mut resp, "/data.txt", &rules);
+
+    assert_eq!(values(&resp, CONTENT_TYPE), ["text/csv"]);
+  }
+}
@@ -1,52 +1,56 @@
 use std::convert::Infallible;
-use std::path::PathBuf;
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
-use futures_util::future::BoxFuture;
-use futures_util::FutureExt;
-use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE, HOST, IF_MODIFIED_SINCE, LAST_MODIFIED};
+use futures_util::future::{ready, BoxFuture};
+use futures_util::{stream, FutureExt, StreamExt};
+use hyper::body::Bytes;
+use hyper::header::{
+  HeaderName, HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, ALLOW, CONTENT_ENCODING, CONTENT_LENGTH,
+  CONTENT_RANGE, CONTENT_TYPE, ETAG, HOST, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE,
+  LAST_MODIFIED, LOCATION, RANGE, VARY,
+};
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
 use mime_guess::Mime;
-use sea_orm::{
-  ColumnTrait, Condition, DatabaseConnection, EntityTrait, JoinType, QueryFilter, QuerySelect,
-  RelationTrait, Select, SelectTwo,
-};
 use time::format_description::well_known::Rfc2822;
 use time::{OffsetDateTime, UtcOffset};
-use tokio::fs::File;
-use tokio_util::codec::BytesCodec;
-use tokio_util::codec::FramedRead;
-
-use view_entity::{commit, environment, file, object};
-
-fn find_object(domain: &str, path: &str) -> Select<object::Entity> {
-  object::Entity::find()
-    .join(JoinType::InnerJoin, object::Relation::File.def())
-    .join(JoinType::InnerJoin, file::Relation::Commit.def())
-    .join(JoinType::InnerJoin, commit::Relation::Environment.def())
-    .filter(
-      Condition::all()
-        .add(file::Column::Path.eq(path))
-        .add(environment::Column::Domain.eq(domain)),
-    )
-}
 
-fn find_fallback_objects(domain: &str) -> SelectTwo<object::Entity, file::Entity> {
-  object::Entity::find()
-    .find_also_related(file::Entity)
-    .join(JoinType::InnerJoin, file::Relation::Commit.def())
-    .join(JoinType::InnerJoin, commit::Relation::Environment.def())
-    .filter(
-      Condition::all()
-        .add(file::Column::Fallback.eq(true))
-        .add(environment::Column::Domain.eq(domain)),
-    )
-}
+use view_entity::object;
+use view_store::{ByteStream, Encoding};
+
+use crate::conditional::{etag, modified_since, none_match, range_matches};
+use crate::encoding::negotiate;
+use crate::headers::apply_header_rules;
+use crate::manifest::{Manifest, ManifestFile, Site};
+use crate::paths::candidates;
+use crate::range::{parse_range, RangeRequest};
+use crate::redirects::{evaluate_redirects, RedirectAction};
+
+mod blob_cache;
+mod conditional;
+mod encoding;
+mod headers;
+mod manifest;
+mod paths;
+mod range;
+mod redirects;
+
+pub use blob_cache::{BlobCache, BlobCacheStats};
+pub use manifest::ManifestCache;
+
+const ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_ROBOTS_TAG: HeaderName = HeaderName::from_static("x-robots-tag");
 
 pub struct FileService {
-  pub root_dir: PathBuf,
-  pub db: DatabaseConnection,
+  pub blobs: Arc<BlobCache>,
+  pub manifests: Arc<ManifestCache>,
+  /// Files serving requests for their directory, in order of preference.
+  pub index_names: Arc<Vec<String>>,
 }
 
 impl Service<Request<Body>> for FileService {
@@ -65,141 +69,492 @@ impl Service<Request<Body>> for FileService {
       .and_then(|value| value.to_str().ok())
       .and_then(|value| value.split(':').next())
       .unwrap_or("localhost")
-      .to_string();
+      .to_ascii_lowercase();
 
-    let db = self.db.clone();
-    let root_dir = self.root_dir.clone();
+    let manifests = self.manifests.clone();
+    let blobs = self.blobs.clone();
+    let index_names = self.index_names.clone();
 
     async move {
-      if req.method() != Method::GET {
-        return Ok(
-          Response::builder()
-            .status(StatusCode::METHOD_NOT_ALLOWED)
-            .body(Body::empty())
-            .unwrap(),
-        );
+      match *req.method() {
+        Method::GET | Method::HEAD => {}
+        Method::OPTIONS => {
+          return Ok(
+            Response::builder()
+              .status(StatusCode::NO_CONTENT)
+              .header(ALLOW, ALLOWED_METHODS)
+              .body(Body::empty())
+              .unwrap(),
+          );
+        }
+        _ => {
+          return Ok(
+            Response::builder()
+              .status(StatusCode::METHOD_NOT_ALLOWED)
+              .header(ALLOW, ALLOWED_METHODS)
+              .body(Body::empty())
+              .unwrap(),
+          );
+        }
       }
 
-      let path = req.uri().path();
-      let select = find_object(&host, path);
-      let object = match select.one(&db).await {
-        Ok(Some(object)) => Ok(Some((object, get_mime_type(path)))),
+      let manifest = match manifests.get(&host).await {
+        Ok(Some(Site::Serve(manifest))) => manifest,
+        Ok(Some(Site::Redirect(domain))) => {
+          let scheme = header(&req, X_FORWARDED_PROTO).unwrap_or("http");
+          let port = header(&req, HOST)
+            .and_then(|value| value.split_once(':'))
+            .map(|(_, port)| format!(":{}", port))
+            .unwrap_or_default();
+          let path = req
+            .uri()
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+
+          return Ok(
+            Response::builder()
+              .status(StatusCode::MOVED_PERMANENTLY)
+              .header(LOCATION, format!("{}://{}{}{}", scheme, domain, port, path))
+              .body(Body::empty())
+              .unwrap(),
+          );
+        }
         Ok(None) => {
-          let path = if !path.ends_with('/') {
-            format!("{}/", path)
-          } else {
-            path.to_string()
-          };
-
-          match find_fallback_objects(&host).all(&db).await {
-            Ok(objects) => Ok(
-              objects
-                .into_iter()
-                .flat_map(|(object, file)| {
-                  let file = file.unwrap();
-
-                  if let Some(idx) = file.path.rfind('/') {
-                    return match &path.strip_prefix(&file.path[..idx + 1]) {
-                      Some(remaining) => Some((object, get_mime_type(&file.path), remaining.len())),
-                      None => None,
-                    };
-                  }
-
-                  Some((object, get_mime_type(&file.path), usize::MAX))
-                })
-                .min_by_key(|(_, _, score)| *score)
-                .map(|(object, mime, _)| (object, mime)),
-            ),
-            Err(err) => Err(err),
-          }
+          return Ok(
+            Response::builder()
+              .status(StatusCode::NOT_FOUND)
+              .body(Body::empty())
+              .unwrap(),
+          )
+        }
+        Err(err) => {
+          eprint!("Error: {:?}", err);
+          return Ok(
+            Response::builder()
+              .status(StatusCode::INTERNAL_SERVER_ERROR)
+              .body(Body::empty())
+              .unwrap(),
+          );
         }
-        Err(err) => Err(err),
       };
 
-      let response = match object {
-        Ok(Some((object, mime))) => {
-          if let Some(modified_since) = req
-            .headers()
-            .get(IF_MODIFIED_SINCE)
-            .and_then(|value| String::from_utf8(value.as_bytes().to_vec()).ok())
-          {
-            match OffsetDateTime::parse(&modified_since, &Rfc2822) {
-              Ok(modified_since) => {
-                if modified_since >= object.created {
-                  return Ok(
-                    Response::builder()
-                      .status(StatusCode::NOT_MODIFIED)
-                      .body(Body::empty())
-                      .unwrap(),
-                  );
-                }
-              }
-              Err(_) => {
-                return Ok(
-                  Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body(Body::empty())
-                    .unwrap(),
-                );
-              }
-            }
-          }
-
-          let path = root_dir
-            .join(hex::encode(&object.id[..1]))
-            .join(hex::encode(&object.id[1..]));
-
-          match File::open(&path).await {
-            Ok(file) => {
-              let stream = FramedRead::new(file, BytesCodec::new());
-              let body = Body::wrap_stream(stream);
-
-              let mut resp = Response::builder()
-                .header(CONTENT_TYPE, mime.essence_str())
-                .header(LAST_MODIFIED, {
-                  let utc_date_time = object
-                    .created
-                    .to_offset(UtcOffset::UTC)
-                    .format(&Rfc2822)
-                    .unwrap();
-                  let utc_date_time = &utc_date_time[..utc_date_time.len() - 5];
-                  format!("{}GMT", utc_date_time)
-                });
-
-              if let Some(size) = object.size {
-                resp = resp.header(CONTENT_LENGTH, size);
-              }
-
-              resp.body(body).unwrap()
-            }
-            Err(err) => {
-              eprint!("Error: {:?}", err);
-              Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::empty())
-                .unwrap()
-            }
-          }
-        }
-        Ok(None) => Response::builder()
-          .status(StatusCode::NOT_FOUND)
+      let mut response = match evaluate_redirects(&manifest.redirect_rules, &host, req.uri()) {
+        Some(RedirectAction::Redirect(status, location)) => Response::builder()
+          .status(status)
+          .header(LOCATION, location)
           .body(Body::empty())
           .unwrap(),
-        Err(err) => {
-          eprint!("Error: {:?}", err);
-          Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(Body::empty())
-            .unwrap()
+        Some(RedirectAction::Rewrite(path)) => {
+          serve(&req, &blobs, &manifest, &index_names, &path, true).await
+        }
+        None => {
+          serve(
+            &req,
+            &blobs,
+            &manifest,
+            &index_names,
+            req.uri().path(),
+            false,
+          )
+          .await
         }
       };
 
+      let status = response.status();
+      if status == StatusCode::NOT_FOUND || status.is_server_error() {
+        if let Some(page) = error_page(&req, &blobs, &manifest, status).await {
+          response = page;
+        }
+      }
+
+      if !response.status().is_server_error() {
+        apply_header_rules(&mut response, req.uri().path(), &manifest.header_rules);
+      }
+
+      // previews must not end up in search results next to the environments
+      if manifest.environment.is_none() {
+        response
+          .headers_mut()
+          .insert(X_ROBOTS_TAG, HeaderValue::from_static("noindex"));
+      }
+
+      // same headers as for GET, the length included, multipart bodies are
+      // only fetched once they are polled
+      if req.method() == Method::HEAD {
+        *response.body_mut() = Body::empty();
+      }
+
       Ok(response)
     }
     .boxed()
   }
 }
 
+/// Looks up the file at the path, or the closest fallback, and responds with
+/// it. Rewritten paths are never redirected to their canonical url, the
+/// client did not ask for them.
+async fn serve(
+  req: &Request<Body>,
+  blobs: &Arc<BlobCache>,
+  manifest: &Manifest,
+  index_names: &[String],
+  path: &str,
+  rewritten: bool,
+) -> Response<Body> {
+  for candidate in candidates(
+    path,
+    manifest.trailing_slash,
+    manifest.clean_urls,
+    index_names,
+  ) {
+    let Some(file) = manifest.file(&candidate.path) else {
+      continue;
+    };
+
+    return match candidate.canonical {
+      Some(location) if !rewritten => {
+        let location = match req.uri().query() {
+          Some(query) => format!("{}?{}", location, query),
+          None => location,
+        };
+
+        Response::builder()
+          .status(StatusCode::MOVED_PERMANENTLY)
+          .header(LOCATION, location)
+          .body(Body::empty())
+          .unwrap()
+      }
+      _ => respond(req, blobs, file).await,
+    };
+  }
+
+  match manifest.fallback(path) {
+    Some(file) => respond(req, blobs, file).await,
+    None => Response::builder()
+      .status(StatusCode::NOT_FOUND)
+      .body(Body::empty())
+      .unwrap(),
+  }
+}
+
+/// Responds with the error page of the current commit for the status. Errors
+/// while loading it are only logged, the plain error is sent instead.
+async fn error_page(
+  req: &Request<Body>,
+  blobs: &Arc<BlobCache>,
+  manifest: &Manifest,
+  status: StatusCode,
+) -> Option<Response<Body>> {
+  let (path, file) = manifest.error_page(status)?;
+
+  let size = file.object.size.map(|size| size as u64);
+  let stream = match open(req, blobs, &file.object.id, None, size, None).await {
+    Ok(stream) => stream?,
+    Err(err) => {
+      eprint!("Error: {:?}", err);
+      return None;
+    }
+  };
+
+  let mut resp = Response::builder()
+    .status(status)
+    .header(CONTENT_TYPE, get_mime_type(path).essence_str());
+
+  if let Some(size) = file.object.size {
+    resp = resp.header(CONTENT_LENGTH, size);
+  }
+
+  Some(resp.body(Body::wrap_stream(stream)).unwrap())
+}
+
+async fn respond(
+  req: &Request<Body>,
+  blobs: &Arc<BlobCache>,
+  file: &ManifestFile,
+) -> Response<Body> {
+  let object = &file.object;
+  let mime = &file.mime;
+  let variants = &file.variants;
+
+  // ranges always refer to the uncompressed content
+  let variant = if req.headers().contains_key(RANGE) {
+    None
+  } else {
+    let available = variants
+      .iter()
+      .map(|(encoding, _)| *encoding)
+      .collect::<Vec<_>>();
+    negotiate(header(req, ACCEPT_ENCODING), &available)
+      .and_then(|encoding| variants.iter().find(|(other, _)| *other == encoding))
+      .copied()
+  };
+
+  let last_modified = http_date(object.created);
+  let etag = etag(&object.id, variant.map(|(encoding, _)| encoding));
+  let vary = !variants.is_empty();
+
+  // If-None-Match takes precedence, If-Modified-Since is only a fallback for
+  // clients without an entity tag
+  let not_modified = match header(req, IF_NONE_MATCH) {
+    Some(value) => !none_match(value, &etag),
+    None => match header(req, IF_MODIFIED_SINCE) {
+      Some(value) => !modified_since(value, object.created),
+      None => false,
+    },
+  };
+
+  if not_modified {
+    let mut resp = Response::builder()
+      .status(StatusCode::NOT_MODIFIED)
+      .header(ETAG, &etag)
+      .header(LAST_MODIFIED, &last_modified);
+
+    if vary {
+      resp = resp.header(VARY, ACCEPT_ENCODING.as_str());
+    }
+
+    return resp.body(Body::empty()).unwrap();
+  }
+
+  // a range of a representation that changed in the meantime is useless,
+  // If-Range asks for the full content in that case
+  let if_range = header(req, IF_RANGE)
+    .map(|value| range_matches(value, &etag, &last_modified))
+    .unwrap_or(true);
+
+  let size = object.size.map(|size| size as u64);
+  let ranges = match (size, req.headers().get(RANGE)) {
+    (Some(size), Some(range)) if if_range => match range.to_str() {
+      Ok(range) => parse_range(range, size),
+      Err(_) => RangeRequest::Full,
+    },
+    _ => RangeRequest::Full,
+  };
+
+  let mut resp = Response::builder()
+    .header(ETAG, &etag)
+    .header(LAST_MODIFIED, &last_modified)
+    .header(ACCEPT_RANGES, "bytes");
+
+  if vary {
+    resp = resp.header(VARY, ACCEPT_ENCODING.as_str());
+  }
+
+  if let Some((encoding, size)) = variant {
+    let result = open(
+      req,
+      blobs,
+      &object.id,
+      Some(encoding),
+      Some(size as u64),
+      None,
+    )
+    .await
+    .map(|stream| {
+      stream.map(|stream| {
+        resp
+          .header(CONTENT_TYPE, mime.essence_str())
+          .header(CONTENT_ENCODING, encoding.name())
+          .header(CONTENT_LENGTH, size)
+          .body(Body::wrap_stream(stream))
+          .unwrap()
+      })
+    });
+
+    return unwrap_response(object, result);
+  }
+
+  let result = match ranges {
+    RangeRequest::Full => open(req, blobs, &object.id, None, size, None)
+      .await
+      .map(|stream| {
+        stream.map(|stream| {
+          let mut resp = resp.header(CONTENT_TYPE, mime.essence_str());
+
+          if let Some(size) = object.size {
+            resp = resp.header(CONTENT_LENGTH, size);
+          }
+
+          resp.body(Body::wrap_stream(stream)).unwrap()
+        })
+      }),
+    RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+      let range = ranges[0].clone();
+
+      open(req, blobs, &object.id, None, size, Some(range.clone()))
+        .await
+        .map(|stream| {
+          stream.map(|stream| {
+            resp
+              .status(StatusCode::PARTIAL_CONTENT)
+              .header(CONTENT_TYPE, mime.essence_str())
+              .header(CONTENT_LENGTH, range.end - range.start)
+              .header(
+                CONTENT_RANGE,
+                format!(
+                  "bytes {}-{}/{}",
+                  range.start,
+                  range.end - 1,
+                  size.unwrap_or_default()
+                ),
+              )
+              .body(Body::wrap_stream(stream))
+              .unwrap()
+          })
+        })
+    }
+    RangeRequest::Partial(ranges) => {
+      let (length, stream) = multipart_byteranges(blobs.clone(), object, mime, ranges);
+
+      Ok(Some(
+        resp
+          .status(StatusCode::PARTIAL_CONTENT)
+          .header(
+            CONTENT_TYPE,
+            format!("multipart/byteranges; boundary={}", boundary(object)),
+          )
+          .header(CONTENT_LENGTH, length)
+          .body(Body::wrap_stream(stream))
+          .unwrap(),
+      ))
+    }
+    RangeRequest::Unsatisfiable => Ok(Some(
+      resp
+        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header(
+          CONTENT_RANGE,
+          format!("bytes */{}", object.size.unwrap_or_default()),
+        )
+        .body(Body::empty())
+        .unwrap(),
+    )),
+  };
+
+  unwrap_response(object, result)
+}
+
+/// Opens the content of a response. HEAD responses are built from the
+/// manifest alone, neither the store nor the cache is asked for the content.
+async fn open(
+  req: &Request<Body>,
+  blobs: &BlobCache,
+  id: &[u8],
+  encoding: Option<Encoding>,
+  size: Option<u64>,
+  range: Option<Range<u64>>,
+) -> anyhow::Result<Option<ByteStream<'static>>> {
+  if req.method() == Method::HEAD {
+    return Ok(Some(stream::empty().boxed()));
+  }
+
+  blobs.get(id, encoding, size, range).await
+}
+
+fn unwrap_response(
+  object: &object::Model,
+  result: anyhow::Result<Option<Response<Body>>>,
+) -> Response<Body> {
+  match result {
+    Ok(Some(resp)) => resp,
+    Ok(None) => {
+      eprint!("Error: object {} is missing", hex::encode(&object.id));
+      Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::empty())
+        .unwrap()
+    }
+    Err(err) => {
+      eprint!("Error: {:?}", err);
+      Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::empty())
+        .unwrap()
+    }
+  }
+}
+
+fn header(req: &Request<Body>, name: HeaderName) -> Option<&str> {
+  req
+    .headers()
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+}
+
+fn boundary(object: &object::Model) -> String {
+  format!("view-{}", hex::encode(&object.id[..8]))
+}
+
+/// Builds a `multipart/byteranges` body, the parts are only fetched from the
+/// store once the previous one has been sent. Returns the length of the body
+/// together with the body.
+fn multipart_byteranges(
+  blobs: Arc<BlobCache>,
+  object: &object::Model,
+  mime: &Mime,
+  ranges: Vec<Range<u64>>,
+) -> (u64, ByteStream<'static>) {
+  let boundary = boundary(object);
+  let size = object.size.unwrap_or_default() as u64;
+
+  let parts = ranges
+    .into_iter()
+    .map(|range| {
+      let header = format!(
+        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+        boundary,
+        mime.essence_str(),
+        range.start,
+        range.end - 1,
+        size
+      );
+      (header, range)
+    })
+    .collect::<Vec<_>>();
+  let trailer = format!("\r\n--{}--\r\n", boundary);
+
+  let length = parts
+    .iter()
+    .map(|(header, range)| header.len() as u64 + range.end - range.start)
+    .sum::<u64>()
+    + trailer.len() as u64;
+
+  let id = object.id.clone();
+  let stream = stream::iter(parts)
+    .then(move |(header, range)| {
+      let blobs = blobs.clone();
+      let id = id.clone();
+
+      async move {
+        let body = match blobs.get(&id, None, Some(size), Some(range)).await {
+          Ok(Some(stream)) => stream,
+          Ok(None) => stream::once(ready(Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("object {} is missing", hex::encode(&id)),
+          ))))
+          .boxed(),
+          Err(err) => stream::once(ready(Err(io::Error::other(err)))).boxed(),
+        };
+
+        stream::once(ready(Ok(Bytes::from(header)))).chain(body)
+      }
+    })
+    .flatten()
+    .chain(stream::once(ready(Ok(Bytes::from(trailer)))))
+    .boxed();
+
+  (length, stream)
+}
+
+fn http_date(date_time: OffsetDateTime) -> String {
+  let utc_date_time = date_time
+    .to_offset(UtcOffset::UTC)
+    .format(&Rfc2822)
+    .unwrap();
+  let utc_date_time = &utc_date_time[..utc_date_time.len() - 5];
+  format!("{}GMT", utc_date_time)
+}
+
 fn get_mime_type(path: &str) -> Mime {
   mime_guess::from_path(path).first_or_octet_stream()
 }
@@ -0,0 +1,571 @@
+use std::collections::{HashMap, HashSet};
+use std::iter::once;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use hyper::StatusCode;
+use lru::LruCache;
+use mime_guess::Mime;
+use sea_orm::{
+  ColumnTrait, DatabaseConnection, DbErr, EntityTrait, JoinType, PaginatorTrait, QueryFilter,
+  QueryOrder, QuerySelect, RelationTrait, Select,
+};
+
+use view_entity::commit::CommitStatus;
+use view_entity::environment::TrailingSlash;
+use view_entity::{
+  commit, domain, environment, error_page, file, header_rule, object, object_variant, redirect_rule,
+};
+use view_store::Encoding;
+
+use crate::get_mime_type;
+
+pub(crate) struct ManifestFile {
+  pub(crate) object: object::Model,
+  pub(crate) mime: Mime,
+  /// Compressed variants with their size.
+  pub(crate) variants: Vec<(Encoding, i64)>,
+}
+
+/// Everything needed to serve a commit, either as the current commit of an
+/// environment or as a preview.
+pub(crate) struct Manifest {
+  /// The environment serving the commit, `None` for previews.
+  pub(crate) environment: Option<environment::Model>,
+  pub(crate) trailing_slash: TrailingSlash,
+  pub(crate) clean_urls: bool,
+  files: HashMap<String, Arc<ManifestFile>>,
+  fallbacks: Vec<String>,
+  pub(crate) redirect_rules: Vec<redirect_rule::Model>,
+  pub(crate) header_rules: Vec<header_rule::Model>,
+  error_pages: Vec<error_page::Model>,
+  /// Variants are made after uploading, the manifest is reloaded once more
+  /// of them exist.
+  variant_rows: u64,
+}
+
+impl Manifest {
+  pub(crate) fn file(&self, path: &str) -> Option<&Arc<ManifestFile>> {
+    self.files.get(path)
+  }
+
+  /// The fallback closest to the path, fallbacks in a parent directory of
+  /// the path are preferred over the others.
+  pub(crate) fn fallback(&self, path: &str) -> Option<&Arc<ManifestFile>> {
+    let path = if !path.ends_with('/') {
+      format!("{}/", path)
+    } else {
+      path.to_string()
+    };
+
+    self
+      .fallbacks
+      .iter()
+      .flat_map(|fallback| {
+        if let Some(idx) = fallback.rfind('/') {
+          return path
+            .strip_prefix(&fallback[..idx + 1])
+            .map(|remaining| (fallback, remaining.len()));
+        }
+
+        Some((fallback, usize::MAX))
+      })
+      .min_by_key(|(_, score)| *score)
+      .and_then(|(fallback, _)| self.files.get(fallback))
+  }
+
+  /// The page for the status, pages for 500 also cover the other 5xx
+  /// statuses.
+  pub(crate) fn error_page(&self, status: StatusCode) -> Option<(&str, &Arc<ManifestFile>)> {
+    let find = |status: StatusCode| {
+      self
+        .error_pages
+        .iter()
+        .find(|page| page.status == status.as_u16() as i16)
+    };
+
+    let page = match find(status) {
+      Some(page) => page,
+      None if status.is_server_error() => find(StatusCode::INTERNAL_SERVER_ERROR)?,
+      None => return None,
+    };
+
+    Some((&page.path, self.files.get(&page.path)?))
+  }
+}
+
+/// What to do with requests for a domain.
+#[derive(Clone)]
+pub(crate) enum Site {
+  Serve(Arc<Manifest>),
+  /// Redirect to the primary domain of the environment.
+  Redirect(String),
+}
+
+struct Entry {
+  site: Site,
+  checked: Instant,
+  /// For wildcard domains, the exact domains it covers. They take
+  /// precedence, so the entry is not used for them.
+  shadowed: HashSet<String>,
+}
+
+struct Preview {
+  /// The commit with the prefix and its manifest, `None` if there is no
+  /// such commit.
+  commit: Option<(Vec<u8>, Arc<Manifest>)>,
+  checked: Instant,
+}
+
+/// Previews are used far less than environments, only the most recently
+/// requested prefixes are kept.
+const PREVIEW_CAPACITY: usize = 32;
+
+/// Hosts without an environment are remembered as well, clients can send any
+/// host so only the most recent ones are kept.
+const UNKNOWN_CAPACITY: usize = 1024;
+
+/// Caches the manifest of every environment by domain, wildcard domains
+/// included. A manifest is used as is for `revalidate_after`, afterwards the
+/// environment is looked up again and the manifest only reloaded if the
+/// environment changed, e.g. to serve another commit, or the variants of its
+/// objects did. Hosts no environment uses are looked up again after
+/// `revalidate_after` as well.
+///
+/// With a preview domain, `<commit id prefix>.<preview domain>` serves the
+/// commit directly. Prefixes are revalidated the same way, in case they
+/// became ambiguous or the commit got more variants.
+pub struct ManifestCache {
+  db: DatabaseConnection,
+  revalidate_after: Duration,
+  preview_domain: Option<String>,
+  entries: RwLock<HashMap<String, Entry>>,
+  /// When the hosts were last found to be unused.
+  unknown: Mutex<LruCache<String, Instant>>,
+  previews: Mutex<LruCache<String, Preview>>,
+}
+
+impl ManifestCache {
+  pub fn new(
+    db: DatabaseConnection,
+    revalidate_after: Duration,
+    preview_domain: Option<String>,
+  ) -> Self {
+    Self {
+      db,
+      revalidate_after,
+      preview_domain,
+      entries: RwLock::new(HashMap::new()),
+      unknown: Mutex::new(LruCache::new(NonZeroUsize::new(UNKNOWN_CAPACITY).unwrap())),
+      previews: Mutex::new(LruCache::new(NonZeroUsize::new(PREVIEW_CAPACITY).unwrap())),
+    }
+  }
+
+  /// Returns how to serve the host, or `None` if no environment uses it.
+  pub(crate) async fn get(&self, host: &str) -> Result<Option<Site>, DbErr> {
+    let preview = self
+      .preview_domain
+      .as_deref()
+      .and_then(|preview_domain| preview_prefix(host, preview_domain));
+
+    if let Some(prefix) = preview {
+      return self.preview(prefix).await;
+    }
+
+    let wildcard = wildcard(host);
+
+    let cached = {
+      let entries = self.entries.read().unwrap();
+      entries
+        .get(host)
+        .or_else(|| {
+          wildcard
+            .as_ref()
+            .and_then(|wildcard| entries.get(wildcard))
+            .filter(|entry| !entry.shadowed.contains(host))
+        })
+        .map(|entry| (entry.site.clone(), entry.checked))
+    };
+
+    if let Some((site, checked)) = cached {
+      if checked.elapsed() < self.revalidate_after {
+        return Ok(Some(site));
+      }
+    }
+
+    let unknown = self.unknown.lock().unwrap().get(host).copied();
+    if unknown.is_some_and(|checked| checked.elapsed() < self.revalidate_after) {
+      return Ok(None);
+    }
+
+    let resolved = match resolve(&self.db, host, wildcard.as_deref()).await? {
+      // the commit was published before commits had a status and some of its
+      // objects are missing, cached manifests are only loaded for complete ones
+      Some((_, environment, false))
+        if !self.serves(&environment.commit_id)
+          && !is_complete(&self.db, &environment.commit_id).await? =>
+      {
+        None
+      }
+      resolved => resolved,
+    };
+
+    let Some((domain, environment, redirect)) = resolved else {
+      {
+        let mut entries = self.entries.write().unwrap();
+        entries.remove(host);
+        if let Some(wildcard) = &wildcard {
+          entries.remove(wildcard);
+        }
+      }
+      self
+        .unknown
+        .lock()
+        .unwrap()
+        .put(host.to_string(), Instant::now());
+      return Ok(None);
+    };
+
+    self.unknown.lock().unwrap().pop(host);
+
+    let site = if redirect {
+      Site::Redirect(environment.domain)
+    } else {
+      // aliases of an environment share its manifest
+      let cached = self.find(|manifest| manifest.environment.as_ref() == Some(&environment));
+
+      let current = match cached {
+        Some(manifest)
+          if count_variants(&self.db, &environment.commit_id).await? == manifest.variant_rows =>
+        {
+          Some(manifest)
+        }
+        _ => None,
+      };
+
+      match current {
+        Some(manifest) => Site::Serve(manifest),
+        None => {
+          let commit_id = environment.commit_id.clone();
+          Site::Serve(Arc::new(
+            load(&self.db, commit_id, Some(environment)).await?,
+          ))
+        }
+      }
+    };
+
+    let shadowed = if domain.starts_with("*.") {
+      exact_domains(&self.db, &domain).await?
+    } else {
+      HashSet::new()
+    };
+
+    let mut entries = self.entries.write().unwrap();
+    if domain != host {
+      entries.remove(host);
+    }
+    entries.insert(
+      domain,
+      Entry {
+        site: site.clone(),
+        checked: Instant::now(),
+        shadowed,
+      },
+    );
+
+    Ok(Some(site))
+  }
+
+  async fn preview(&self, prefix: &str) -> Result<Option<Site>, DbErr> {
+    let cached = self
+      .previews
+      .lock()
+      .unwrap()
+      .get(prefix)
+      .map(|preview| (preview.commit.clone(), preview.checked));
+
+    if let Some((commit, checked)) = &cached {
+      if checked.elapsed() < self.revalidate_after {
+        return Ok(commit.clone().map(|(_, manifest)| Site::Serve(manifest)));
+      }
+    }
+
+    let Some(commit_id) = find_commit(&self.db, prefix).await? else {
+      self.previews.lock().unwrap().put(
+        prefix.to_string(),
+        Preview {
+          commit: None,
+          checked: Instant::now(),
+        },
+      );
+      return Ok(None);
+    };
+
+    // other prefixes of the commit share its manifest
+    let cached = cached
+      .and_then(|(commit, _)| commit)
+      .filter(|(id, _)| *id == commit_id)
+      .or_else(|| {
+        self
+          .previews
+          .lock()
+          .unwrap()
+          .iter()
+          .find_map(|(_, preview)| preview.commit.clone().filter(|(id, _)| *id == commit_id))
+      });
+
+    let current = match cached {
+      Some((_, manifest))
+        if count_variants(&self.db, &commit_id).await? == manifest.variant_rows =>
+      {
+        Some(manifest)
+      }
+      _ => None,
+    };
+
+    let manifest = match current {
+      Some(manifest) => manifest,
+      None => Arc::new(load(&self.db, commit_id.clone(), None).await?),
+    };
+
+    self.previews.lock().unwrap().put(
+      prefix.to_string(),
+      Preview {
+        commit: Some((commit_id, manifest.clone())),
+        checked: Instant::now(),
+      },
+    );
+
+    Ok(Some(Site::Serve(manifest)))
+  }
+
+  /// Whether a cached manifest of an environment serves the commit.
+  fn serves(&self, commit_id: &[u8]) -> bool {
+    self
+      .find(|manifest| {
+        manifest
+          .environment
+          .as_ref()
+          .is_some_and(|environment| environment.commit_id == commit_id)
+      })
+      .is_some()
+  }
+
+  fn find(&self, predicate: impl Fn(&Manifest) -> bool) -> Option<Arc<Manifest>> {
+    self
+      .entries
+      .read()
+      .unwrap()
+      .values()
+      .find_map(|entry| match &entry.site {
+        Site::Serve(manifest) if predicate(manifest) => Some(manifest.clone()),
+        _ => None,
+      })
+  }
+}
+
+/// The commit id prefix of a preview host, git's short commit ids have at
+/// least 7 characters.
+fn preview_prefix<'a>(host: &'a str, preview_domain: &str) -> Option<&'a str> {
+  let prefix = host.strip_suffix(preview_domain)?.strip_suffix('.')?;
+
+  let valid = (7..=40).contains(&prefix.len()) && prefix.chars().all(|c| c.is_ascii_hexdigit());
+
+  valid.then_some(prefix)
+}
+
+/// Finds the complete commit whose id starts with the hex prefix, `None` if
+/// there is no such commit or the prefix is ambiguous.
+async fn find_commit(db: &DatabaseConnection, prefix: &str) -> Result<Option<Vec<u8>>, DbErr> {
+  // ids sharing the prefix lie between the prefix padded with the lowest and
+  // the highest hex digit
+  let first = hex::decode(format!("{:0<40}", prefix)).unwrap();
+  let last = hex::decode(format!("{:f<40}", prefix)).unwrap();
+
+  let commits = commit::Entity::find()
+    .filter(commit::Column::Id.between(first, last))
+    .filter(commit::Column::Status.eq(CommitStatus::Complete))
+    .limit(2)
+    .all(db)
+    .await?;
+
+  match commits.as_slice() {
+    [commit] => Ok(Some(commit.id.clone())),
+    _ => Ok(None),
+  }
+}
+
+async fn is_complete(db: &DatabaseConnection, commit_id: &[u8]) -> Result<bool, DbErr> {
+  let commit = commit::Entity::find_by_id(commit_id.to_vec())
+    .one(db)
+    .await?;
+
+  Ok(commit.is_some_and(|commit| commit.status == CommitStatus::Complete))
+}
+
+/// The wildcard domain matching the host, `*.example.com` for
+/// `www.example.com`.
+fn wildcard(host: &str) -> Option<String> {
+  host
+    .split_once('.')
+    .map(|(_, parent)| format!("*.{}", parent))
+}
+
+/// The exact domains covered by the wildcard domain, of environments and
+/// aliases alike.
+async fn exact_domains(
+  db: &DatabaseConnection,
+  wildcard_domain: &str,
+) -> Result<HashSet<String>, DbErr> {
+  // domains never contain `%` or `_`, the parent can be used as is
+  let pattern = format!("%{}", &wildcard_domain[1..]);
+
+  let environments = environment::Entity::find()
+    .filter(environment::Column::Domain.like(&pattern))
+    .all(db)
+    .await?
+    .into_iter()
+    .map(|environment| environment.domain);
+
+  let aliases = domain::Entity::find()
+    .filter(domain::Column::Name.like(&pattern))
+    .all(db)
+    .await?
+    .into_iter()
+    .map(|domain| domain.name);
+
+  Ok(
+    environments
+      .chain(aliases)
+      .filter(|domain| wildcard(domain).as_deref() == Some(wildcard_domain))
+      .collect(),
+  )
+}
+
+/// Finds the environment using the host, exact domains take precedence over
+/// wildcard ones. Returns the matching domain, the environment and whether
+/// to redirect to its primary domain.
+async fn resolve(
+  db: &DatabaseConnection,
+  host: &str,
+  wildcard: Option<&str>,
+) -> Result<Option<(String, environment::Model, bool)>, DbErr> {
+  for domain in once(host).chain(wildcard) {
+    let environment = environment::Entity::find()
+      .filter(environment::Column::Domain.eq(domain))
+      .one(db)
+      .await?;
+
+    if let Some(environment) = environment {
+      return Ok(Some((domain.to_string(), environment, false)));
+    }
+
+    let alias = domain::Entity::find_by_id(domain.to_string())
+      .find_also_related(environment::Entity)
+      .one(db)
+      .await?;
+
+    if let Some((alias, Some(environment))) = alias {
+      // there is no single host to redirect a wildcard domain to
+      let redirect = alias.redirect && !environment.domain.starts_with("*.");
+      return Ok(Some((domain.to_string(), environment, redirect)));
+    }
+  }
+
+  Ok(None)
+}
+
+fn variants_of(commit_id: Vec<u8>) -> Select<object_variant::Entity> {
+  object_variant::Entity::find()
+    .join(JoinType::InnerJoin, object_variant::Relation::Object.def())
+    .join(JoinType::InnerJoin, object::Relation::File.def())
+    .filter(file::Column::CommitId.eq(commit_id))
+}
+
+async fn count_variants(db: &DatabaseConnection, commit_id: &[u8]) -> Result<u64, DbErr> {
+  variants_of(commit_id.to_vec()).count(db).await
+}
+
+async fn load(
+  db: &DatabaseConnection,
+  commit_id: Vec<u8>,
+  environment: Option<environment::Model>,
+) -> Result<Manifest, DbErr> {
+  // previews use the defaults of new environments
+  let (trailing_slash, clean_urls) = match &environment {
+    Some(environment) => (environment.trailing_slash, environment.clean_urls),
+    None => (TrailingSlash::Preserve, false),
+  };
+
+  let variant_rows = variants_of(commit_id.clone()).all(db).await?;
+  let variant_count = variant_rows.len() as u64;
+
+  let mut variants = HashMap::<Vec<u8>, Vec<(Encoding, i64)>>::new();
+  for variant in variant_rows {
+    let Some(encoding) = Encoding::from_name(&variant.encoding) else {
+      continue;
+    };
+
+    // objects of several files are joined once per file
+    let object_variants = variants.entry(variant.object_id).or_default();
+    if !object_variants.contains(&(encoding, variant.size)) {
+      object_variants.push((encoding, variant.size));
+    }
+  }
+
+  let mut files = HashMap::new();
+  let mut fallbacks = Vec::new();
+
+  for (file, object) in file::Entity::find()
+    .find_also_related(object::Entity)
+    .filter(file::Column::CommitId.eq(commit_id.clone()))
+    .all(db)
+    .await?
+  {
+    let Some(object) = object else {
+      continue;
+    };
+
+    if file.fallback {
+      fallbacks.push(file.path.clone());
+    }
+
+    let manifest_file = ManifestFile {
+      variants: variants.get(&object.id).cloned().unwrap_or_default(),
+      mime: get_mime_type(&file.path),
+      object,
+    };
+
+    files.insert(file.path, Arc::new(manifest_file));
+  }
+
+  let redirect_rules = redirect_rule::Entity::find()
+    .filter(redirect_rule::Column::CommitId.eq(commit_id.clone()))
+    .order_by_asc(redirect_rule::Column::Position)
+    .all(db)
+    .await?;
+
+  let header_rules = header_rule::Entity::find()
+    .filter(header_rule::Column::CommitId.eq(commit_id.clone()))
+    .order_by_asc(header_rule::Column::Position)
+    .all(db)
+    .await?;
+
+  let error_pages = error_page::Entity::find()
+    .filter(error_page::Column::CommitId.eq(commit_id))
+    .all(db)
+    .await?;
+
+  Ok(Manifest {
+    environment,
+    trailing_slash,
+    clean_urls,
+    files,
+    fallbacks,
+    redirect_rules,
+    header_rules,
+    error_pages,
+    variant_rows: variant_count,
+  })
+}
@@ -0,0 +1,238 @@
+use view_entity::environment::TrailingSlash;
+
+/// A path to look up for a request. If it exists, but the request did not use
+/// the canonical url for it, the client is redirected to `canonical`.
+pub(crate) struct Candidate {
+  pub(crate) path: String,
+  pub(crate) canonical: Option<String>,
+}
+
+impl Candidate {
+  fn new(path: String, canonical: Option<String>) -> Self {
+    Self { path, canonical }
+  }
+}
+
+/// Lists the paths a request may be served from in the order they are tried:
+/// the path itself, the index files of the directory and, with clean urls,
+/// the `.html` file.
+pub(crate) fn candidates(
+  path: &str,
+  policy: TrailingSlash,
+  clean_urls: bool,
+  index_names: &[String],
+) -> Vec<Candidate> {
+  let mut candidates = Vec::new();
+
+  if let Some(base) = path.strip_suffix('/') {
+    let canonical = (policy == TrailingSlash::Never && !base.is_empty()).then(|| base.to_string());
+
+    for index in index_names {
+      candidates.push(Candidate::new(
+        format!("{}/{}", base, index),
+        canonical.clone(),
+      ));
+    }
+
+    if clean_urls && !base.is_empty() {
+      candidates.push(Candidate::new(format!("{}.html", base), canonical));
+    }
+
+    return candidates;
+  }
+
+  let canonical = match path.strip_suffix(".html") {
+    Some(stem) if clean_urls => Some(clean_url(stem, policy, index_names)),
+    _ => None,
+  };
+  candidates.push(Candidate::new(path.to_string(), canonical));
+
+  let canonical = (policy == TrailingSlash::Always).then(|| format!("{}/", path));
+
+  for index in index_names {
+    candidates.push(Candidate::new(
+      format!("{}/{}", path, index),
+      canonical.clone(),
+    ));
+  }
+
+  if clean_urls {
+    candidates.push(Candidate::new(format!("{}.html", path), canonical));
+  }
+
+  candidates
+}
+
+/// Canonical url of a `.html` file, index files are addressed by their
+/// directory.
+fn clean_url(stem: &str, policy: TrailingSlash, index_names: &[String]) -> String {
+  let (dir, name) = stem.rsplit_once('/').unwrap_or(("", stem));
+
+  let is_index = index_names
+    .iter()
+    .any(|index| index.strip_suffix(".html") == Some(name));
+
+  match (is_index, policy) {
+    (true, TrailingSlash::Never) if !dir.is_empty() => dir.to_string(),
+    (true, _) => format!("{}/", dir),
+    (false, TrailingSlash::Always) => format!("{}/", stem),
+    (false, _) => stem.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn list(path: &str, policy: TrailingSlash, clean_urls: bool) -> Vec<(String, Option<String>)> {
+    candidates(path, policy, clean_urls, &["index.html".to_string()])
+      .into_iter()
+      .map(|candidate| (candidate.path, candidate.canonical))
+      .collect()
+  }
+
+  fn expected(candidates: &[(&str, Option<&str>)]) -> Vec<(String, Option<String>)> {
+    candidates
+      .iter()
+      .map(|(path, canonical)| (path.to_string(), canonical.map(str::to_string)))
+      .collect()
+  }
+
+  #[test]
+  fn preserves_both_forms() {
+    for clean_urls in [false, true] {
+      assert_eq!(
+        list("/", TrailingSlash::Preserve, clean_urls),
+        expected(&[("/index.html", None)])
+      );
+    }
+
+    assert_eq!(
+      list("/docs/", TrailingSlash::Preserve, false),
+      expected(&[("/docs/index.html", None)])
+    );
+    assert_eq!(
+      list("/docs", TrailingSlash::Preserve, false),
+      expected(&[("/docs", None), ("/docs/index.html", None)])
+    );
+    assert_eq!(
+      list("/docs/", TrailingSlash::Preserve, true),
+      expected(&[("/docs/index.html", None), ("/docs.html", None)])
+    );
+    assert_eq!(
+      list("/docs", TrailingSlash::Preserve, true),
+      expected(&[
+        ("/docs", None),
+        ("/docs/index.html", None),
+        ("/docs.html", None),
+      ])
+    );
+  }
+
+  #[test]
+  fn redirects_to_a_trailing_slash() {
+    assert_eq!(
+      list("/docs/", TrailingSlash::Always, false),
+      expected(&[("/docs/index.html", None)])
+    );
+    assert_eq!(
+      list("/docs", TrailingSlash::Always, false),
+      expected(&[("/docs", None), ("/docs/index.html", Some("/docs/"))])
+    );
+    assert_eq!(
+      list("/docs/", TrailingSlash::Always, true),
+      expected(&[("/docs/index.html", None), ("/docs.html", None)])
+    );
+    assert_eq!(
+      list("/docs", TrailingSlash::Always, true),
+      expected(&[
+        ("/docs", None),
+        ("/docs/index.html", Some("/docs/")),
+        ("/docs.html", Some("/docs/")),
+      ])
+    );
+  }
+
+  #[test]
+  fn redirects_away_from_a_trailing_slash() {
+    assert_eq!(
+      list("/", TrailingSlash::Never, true),
+      expected(&[("/index.html", None)])
+    );
+    assert_eq!(
+      list("/docs/", TrailingSlash::Never, false),
+      expected(&[("/docs/index.html", Some("/docs"))])
+    );
+    assert_eq!(
+      list("/docs", TrailingSlash::Never, false),
+      expected(&[("/docs", None), ("/docs/index.html", None)])
+    );
+    assert_eq!(
+      list("/docs/", TrailingSlash::Never, true),
+      expected(&[
+        ("/docs/index.html", Some("/docs")),
+        ("/docs.html", Some("/docs")),
+      ])
+    );
+    assert_eq!(
+      list("/docs", TrailingSlash::Never, true),
+      expected(&[
+        ("/docs", None),
+        ("/docs/index.html", None),
+        ("/docs.html", None),
+      ])
+    );
+  }
+
+  #[test]
+  fn redirects_html_files_to_clean_urls() {
+    assert_eq!(
+      list("/about.html", TrailingSlash::Preserve, false),
+      expected(&[("/about.html", None), ("/about.html/index.html", None)])
+    );
+
+    for (policy, canonical) in [
+      (TrailingSlash::Preserve, "/about"),
+      (TrailingSlash::Always, "/about/"),
+      (TrailingSlash::Never, "/about"),
+    ] {
+      assert_eq!(
+        list("/about.html", policy, true)[0].1.as_deref(),
+        Some(canonical)
+      );
+    }
+  }
+
+  #[test]
+  fn redirects_index_files_to_their_directory() {
+    for (path, policy, canonical) in [
+      ("/docs/index.html", TrailingSlash::Preserve, "/docs/"),
+      ("/docs/index.html", TrailingSlash::Always, "/docs/"),
+      ("/docs/index.html", TrailingSlash::Never, "/docs"),
+      ("/index.html", TrailingSlash::Never, "/"),
+    ] {
+      assert_eq!(
+        list(path, policy, true)[0].1.as_deref(),
+        Some(canonical),
+        "{}",
+        path
+      );
+    }
+
+    assert_eq!(
+      list("/docs/index.html", TrailingSlash::Never, false)[0].1,
+      None
+    );
+  }
+
+  #[test]
+  fn tries_index_names_in_order() {
+    let index_names = ["index.html".to_string(), "index.htm".to_string()];
+    let paths = candidates("/docs/", TrailingSlash::Preserve, false, &index_names)
+      .into_iter()
+      .map(|candidate| candidate.path)
+      .collect::<Vec<_>>();
+
+    assert_eq!(paths, ["/docs/index.html", "/docs/index.htm"]);
+  }
+}
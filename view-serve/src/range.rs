@@ -0,0 +1,162 @@
+use std::ops::Range;
+
+/// Upper bound of ranges per request, more are answered with the full
+/// content instead of an expensive multipart response.
+const MAX_RANGES: usize = 16;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RangeRequest {
+  Full,
+  Partial(Vec<Range<u64>>),
+  Unsatisfiable,
+}
+
+/// Resolves the value of a `Range` header against the size of the content.
+/// Headers that can not be parsed are ignored, as required by RFC 9110.
+pub(crate) fn parse_range(header: &str, size: u64) -> RangeRequest {
+  let specs = match header.trim().strip_prefix("bytes=") {
+    Some(specs) => specs,
+    None => return RangeRequest::Full,
+  };
+
+  let mut ranges = Vec::new();
+  let mut count = 0;
+
+  for spec in specs.split(',') {
+    let spec = spec.trim();
+    if spec.is_empty() {
+      continue;
+    }
+
+    count += 1;
+    if count > MAX_RANGES {
+      return RangeRequest::Full;
+    }
+
+    let (start, end) = match spec.split_once('-') {
+      Some(bounds) => bounds,
+      None => return RangeRequest::Full,
+    };
+
+    let range = match (start.parse::<u64>(), end) {
+      // bytes=-500, the last 500 bytes
+      (Err(_), end) if start.is_empty() => match end.parse::<u64>() {
+        Ok(0) => None,
+        Ok(length) => Some(size.saturating_sub(length)..size),
+        Err(_) => return RangeRequest::Full,
+      },
+      // bytes=500-, everything from the 500th byte
+      (Ok(start), "") => Some(start..size),
+      // bytes=500-999, inclusive
+      (Ok(start), end) => match end.parse::<u64>() {
+        Ok(end) if end >= start => Some(start..size.min(end + 1)),
+        _ => return RangeRequest::Full,
+      },
+      (Err(_), _) => return RangeRequest::Full,
+    };
+
+    if let Some(range) = range {
+      if range.start < size {
+        ranges.push(range);
+      }
+    }
+  }
+
+  if count == 0 {
+    return RangeRequest::Full;
+  }
+
+  if ranges.is_empty() {
+    return RangeRequest::Unsatisfiable;
+  }
+
+  RangeRequest::Partial(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn partial(ranges: &[(u64, u64)]) -> RangeRequest {
+    RangeRequest::Partial(ranges.iter().map(|&(start, end)| start..end).collect())
+  }
+
+  #[test]
+  fn parses_suffix_ranges() {
+    assert_eq!(parse_range("bytes=-500", 1000), partial(&[(500, 1000)]));
+    assert_eq!(parse_range("bytes=-2000", 1000), partial(&[(0, 1000)]));
+    assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+  }
+
+  #[test]
+  fn parses_open_ranges() {
+    assert_eq!(parse_range("bytes=500-", 1000), partial(&[(500, 1000)]));
+    assert_eq!(parse_range("bytes=0-", 1000), partial(&[(0, 1000)]));
+    assert_eq!(
+      parse_range("bytes=1000-", 1000),
+      RangeRequest::Unsatisfiable
+    );
+  }
+
+  #[test]
+  fn parses_closed_ranges() {
+    assert_eq!(parse_range("bytes=0-0", 1000), partial(&[(0, 1)]));
+    assert_eq!(
+      parse_range(" bytes=500-999 ", 1000),
+      partial(&[(500, 1000)])
+    );
+  }
+
+  #[test]
+  fn clamps_end_to_size() {
+    assert_eq!(parse_range("bytes=900-999", 1000), partial(&[(900, 1000)]));
+    assert_eq!(parse_range("bytes=900-5000", 1000), partial(&[(900, 1000)]));
+  }
+
+  #[test]
+  fn keeps_multiple_and_overlapping_ranges() {
+    assert_eq!(
+      parse_range("bytes=0-1, 5-6,-2", 10),
+      partial(&[(0, 2), (5, 7), (8, 10)])
+    );
+    assert_eq!(
+      parse_range("bytes=0-499,400-599", 1000),
+      partial(&[(0, 500), (400, 600)])
+    );
+  }
+
+  #[test]
+  fn drops_unsatisfiable_ranges() {
+    assert_eq!(
+      parse_range("bytes=1000-1100", 1000),
+      RangeRequest::Unsatisfiable
+    );
+    assert_eq!(parse_range("bytes=0-1,2000-", 1000), partial(&[(0, 2)]));
+    assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+  }
+
+  #[test]
+  fn ignores_too_many_ranges() {
+    let header = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+
+    assert_eq!(parse_range(&header, 1000), RangeRequest::Full);
+  }
+
+  #[test]
+  fn ignores_malformed_headers() {
+    for header in [
+      "items=0-1",
+      "bytes 0-1",
+      "bytes=",
+      "bytes=,",
+      "bytes=abc",
+      "bytes=1",
+      "bytes=5-1",
+      "bytes=1-2-3",
+      "bytes=-x",
+      "bytes=0-1,x-",
+    ] {
+      assert_eq!(parse_range(header, 1000), RangeRequest::Full, "{}", header);
+    }
+  }
+}
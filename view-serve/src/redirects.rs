@@ -0,0 +1,307 @@
+use hyper::{StatusCode, Uri};
+
+use view_entity::redirect_rule;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RedirectAction {
+  Redirect(StatusCode, String),
+  /// Serve the content of another path of the commit.
+  Rewrite(String),
+}
+
+/// Evaluates the rules in order, the first one matching the request decides.
+pub(crate) fn evaluate_redirects(
+  rules: &[redirect_rule::Model],
+  host: &str,
+  uri: &Uri,
+) -> Option<RedirectAction> {
+  rules.iter().find_map(|rule| {
+    if rule
+      .host
+      .as_deref()
+      .is_some_and(|other| !other.eq_ignore_ascii_case(host))
+    {
+      return None;
+    }
+
+    let mut params = Vec::new();
+
+    if !match_path(&rule.source, uri.path(), &mut params) {
+      return None;
+    }
+
+    if let Some(query) = &rule.query {
+      if !match_query(query, uri.query().unwrap_or_default(), &mut params) {
+        return None;
+      }
+    }
+
+    let mut destination = substitute(&rule.destination, &params);
+
+    if rule.status == 200 {
+      // the rewritten path is looked up as is, without a query
+      if let Some(idx) = destination.find('?') {
+        destination.truncate(idx);
+      }
+
+      return Some(RedirectAction::Rewrite(destination));
+    }
+
+    // pass the query on, unless the rule consumed or replaced it
+    if let (None, false, Some(query)) = (&rule.query, destination.contains('?'), uri.query()) {
+      destination = format!("{}?{}", destination, query);
+    }
+
+    let status = StatusCode::from_u16(rule.status as u16).ok()?;
+    Some(RedirectAction::Redirect(status, destination))
+  })
+}
+
+/// Matches the path segment by segment, `:name` segments match any single
+/// segment, a trailing `*` the rest of the path. Trailing slashes are
+/// ignored on both sides.
+fn match_path(source: &str, path: &str, params: &mut Vec<(String, String)>) -> bool {
+  let mut segments = trim_slash(path).split('/');
+
+  for expected in trim_slash(source).split('/') {
+    if expected == "*" {
+      params.push(("splat".to_string(), segments.collect::<Vec<_>>().join("/")));
+      return true;
+    }
+
+    let Some(segment) = segments.next() else {
+      return false;
+    };
+
+    match expected.strip_prefix(':') {
+      Some(name) if !segment.is_empty() => params.push((name.to_string(), segment.to_string())),
+      Some(_) => return false,
+      None if expected != segment => return false,
+      None => {}
+    }
+  }
+
+  segments.next().is_none()
+}
+
+fn trim_slash(path: &str) -> &str {
+  match path.strip_suffix('/') {
+    Some(trimmed) if !trimmed.is_empty() => trimmed,
+    _ => path,
+  }
+}
+
+/// Requires every `key=value` condition to be present in the query, values
+/// starting with `:` match anything and capture it.
+fn match_query(conditions: &str, query: &str, params: &mut Vec<(String, String)>) -> bool {
+  let query = query
+    .split('&')
+    .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+    .collect::<Vec<_>>();
+
+  conditions.split('&').all(|condition| {
+    let (key, expected) = condition.split_once('=').unwrap_or((condition, ""));
+
+    let Some((_, value)) = query.iter().find(|(other, _)| *other == key) else {
+      return false;
+    };
+
+    match expected.strip_prefix(':') {
+      Some(name) => {
+        params.push((name.to_string(), value.to_string()));
+        true
+      }
+      None => expected == *value,
+    }
+  })
+}
+
+/// Replaces `:name` in the destination with the captured values. Names have
+/// to start with a letter, which leaves schemes and ports alone.
+fn substitute(destination: &str, params: &[(String, String)]) -> String {
+  let mut result = String::with_capacity(destination.len());
+  let mut rest = destination;
+
+  while let Some(idx) = rest.find(':') {
+    result.push_str(&rest[..idx]);
+    rest = &rest[idx + 1..];
+
+    let end = rest
+      .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+      .unwrap_or(rest.len());
+    let name = &rest[..end];
+
+    match params.iter().find(|(other, _)| other == name) {
+      Some((_, value)) if name.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+        result.push_str(value);
+        rest = &rest[end..];
+      }
+      _ => result.push(':'),
+    }
+  }
+
+  result.push_str(rest);
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rule(source: &str, destination: &str, status: i16) -> redirect_rule::Model {
+    redirect_rule::Model {
+      commit_id: vec![0; 20],
+      position: 0,
+      source: source.to_string(),
+      destination: destination.to_string(),
+      status,
+      query: None,
+      host: None,
+    }
+  }
+
+  fn evaluate(rules: &[redirect_rule::Model], uri: &str) -> Option<RedirectAction> {
+    evaluate_redirects(rules, "example.com", &uri.parse().unwrap())
+  }
+
+  fn redirect(status: StatusCode, destination: &str) -> Option<RedirectAction> {
+    Some(RedirectAction::Redirect(status, destination.to_string()))
+  }
+
+  fn matches(source: &str, path: &str) -> Option<Vec<(String, String)>> {
+    let mut params = Vec::new();
+    match_path(source, path, &mut params).then_some(params)
+  }
+
+  fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn matches_exact_paths() {
+    assert_eq!(matches("/old", "/old"), Some(vec![]));
+    assert_eq!(matches("/old/", "/old"), Some(vec![]));
+    assert_eq!(matches("/old", "/old/"), Some(vec![]));
+    assert_eq!(matches("/", "/"), Some(vec![]));
+    assert_eq!(matches("/old", "/older"), None);
+    assert_eq!(matches("/old", "/old/page"), None);
+    assert_eq!(matches("/old/page", "/old"), None);
+  }
+
+  #[test]
+  fn matches_placeholders() {
+    assert_eq!(
+      matches("/blog/:year/:slug", "/blog/2023/hello"),
+      Some(params(&[("year", "2023"), ("slug", "hello")]))
+    );
+    assert_eq!(matches("/blog/:year/:slug", "/blog/2023"), None);
+    assert_eq!(matches("/blog/:year", "/blog//"), None);
+    assert_eq!(matches("/blog/:year", "/blog/2023/hello"), None);
+  }
+
+  #[test]
+  fn matches_splats() {
+    assert_eq!(
+      matches("/docs/*", "/docs/a/b/c.html"),
+      Some(params(&[("splat", "a/b/c.html")]))
+    );
+    assert_eq!(matches("/docs/*", "/docs"), Some(params(&[("splat", "")])));
+    assert_eq!(
+      matches("/:lang/*", "/en/guide/"),
+      Some(params(&[("lang", "en"), ("splat", "guide")]))
+    );
+    assert_eq!(matches("/docs/*", "/blog/a"), None);
+  }
+
+  #[test]
+  fn substitutes_placeholders() {
+    let params = params(&[("year", "2023"), ("slug", "hello"), ("splat", "a/b")]);
+
+    assert_eq!(substitute("/news/:year/:slug", &params), "/news/2023/hello");
+    assert_eq!(substitute("/archive/:splat", &params), "/archive/a/b");
+    assert_eq!(substitute("/:year-:slug.html", &params), "/2023-hello.html");
+    assert_eq!(substitute("/:unknown", &params), "/:unknown");
+    assert_eq!(
+      substitute("https://example.com:8443/:slug", &params),
+      "https://example.com:8443/hello"
+    );
+  }
+
+  #[test]
+  fn uses_the_status_of_the_first_match() {
+    let rules = [
+      rule("/temporary", "/new", 302),
+      rule("/permanent", "/new", 308),
+      rule("/*", "/fallback", 301),
+    ];
+
+    assert_eq!(
+      evaluate(&rules, "/temporary"),
+      redirect(StatusCode::FOUND, "/new")
+    );
+    assert_eq!(
+      evaluate(&rules, "/permanent"),
+      redirect(StatusCode::PERMANENT_REDIRECT, "/new")
+    );
+    assert_eq!(
+      evaluate(&rules, "/other"),
+      redirect(StatusCode::MOVED_PERMANENTLY, "/fallback")
+    );
+  }
+
+  #[test]
+  fn rewrites_without_query() {
+    let rules = [rule("/app/*", "/app/index.html?from=:splat", 200)];
+
+    assert_eq!(
+      evaluate(&rules, "/app/settings?tab=1"),
+      Some(RedirectAction::Rewrite("/app/index.html".to_string()))
+    );
+  }
+
+  #[test]
+  fn passes_the_query_on() {
+    let rules = [rule("/old", "/new", 301), rule("/search", "/find?all", 302)];
+
+    assert_eq!(
+      evaluate(&rules, "/old?page=2"),
+      redirect(StatusCode::MOVED_PERMANENTLY, "/new?page=2")
+    );
+    assert_eq!(
+      evaluate(&rules, "/search?q=x"),
+      redirect(StatusCode::FOUND, "/find?all")
+    );
+  }
+
+  #[test]
+  fn captures_query_conditions() {
+    let mut search = rule("/search", "/find?term=:query", 302);
+    search.query = Some("q=:query".to_string());
+
+    let rules = [search];
+
+    assert_eq!(
+      evaluate(&rules, "/search?q=rust&page=2"),
+      redirect(StatusCode::FOUND, "/find?term=rust")
+    );
+    assert_eq!(evaluate(&rules, "/search?page=2"), None);
+  }
+
+  #[test]
+  fn only_matches_the_rule_host() {
+    let mut old = rule("/*", "https://example.com/:splat", 308);
+    old.host = Some("old.example.com".to_string());
+
+    let rules = [old];
+    let uri = "/a".parse().unwrap();
+
+    assert_eq!(
+      evaluate_redirects(&rules, "OLD.example.com", &uri),
+      redirect(StatusCode::PERMANENT_REDIRECT, "https://example.com/a")
+    );
+    assert_eq!(evaluate_redirects(&rules, "example.com", &uri), None);
+  }
+}
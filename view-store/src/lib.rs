@@ -0,0 +1,129 @@
+use std::io;
+use std::ops::Range;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+
+pub use local::LocalStore;
+pub use s3::{S3Config, S3Store};
+pub use staging::sweep_staging;
+
+mod local;
+mod s3;
+mod staging;
+
+pub type ByteStream<'a> = BoxStream<'a, io::Result<Bytes>>;
+
+/// Content addressed storage for the blobs of objects, keyed by their
+/// SHA-256 hash.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+  /// Stores the stream under the given id. Returns the size of the content or
+  /// `None` if it does not hash to the id, in which case nothing is stored.
+  async fn put(&self, id: &[u8], stream: ByteStream<'_>) -> anyhow::Result<Option<u64>>;
+
+  /// Streams the content, or only the given byte range of it. Returns `None`
+  /// if nothing is stored under the id.
+  async fn get(
+    &self,
+    id: &[u8],
+    range: Option<Range<u64>>,
+  ) -> anyhow::Result<Option<ByteStream<'static>>>;
+
+  /// Stores a compressed variant of the content, which is served to clients
+  /// accepting the encoding. The content itself has to be stored already.
+  async fn put_variant(&self, id: &[u8], encoding: Encoding, content: Bytes) -> anyhow::Result<()>;
+
+  /// Streams a compressed variant of the content. Returns `None` if the
+  /// variant is not stored.
+  async fn get_variant(
+    &self,
+    id: &[u8],
+    encoding: Encoding,
+  ) -> anyhow::Result<Option<ByteStream<'static>>>;
+
+  async fn exists(&self, id: &[u8]) -> anyhow::Result<bool>;
+
+  /// Deletes the content together with all of its variants.
+  async fn delete(&self, id: &[u8]) -> anyhow::Result<()>;
+
+  /// Deletes a single variant, the content and other variants are kept.
+  async fn delete_variant(&self, id: &[u8], encoding: Encoding) -> anyhow::Result<()>;
+
+  /// Lists the ids of all stored objects.
+  async fn list(&self) -> anyhow::Result<Vec<Vec<u8>>>;
+
+  /// Lists all stored variants, whether their content is stored or not.
+  async fn list_variants(&self) -> anyhow::Result<Vec<(Vec<u8>, Encoding)>>;
+}
+
+/// Content codings the variants of an object can be stored in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+  Brotli,
+  Zstd,
+  Gzip,
+}
+
+impl Encoding {
+  /// All encodings, the ones compressing best come first.
+  pub const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+  /// Name of the content coding as used in `Accept-Encoding` and
+  /// `Content-Encoding`.
+  pub fn name(self) -> &'static str {
+    match self {
+      Encoding::Brotli => "br",
+      Encoding::Zstd => "zstd",
+      Encoding::Gzip => "gzip",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    Encoding::ALL
+      .into_iter()
+      .find(|encoding| encoding.name().eq_ignore_ascii_case(name))
+  }
+
+  fn extension(self) -> &'static str {
+    match self {
+      Encoding::Brotli => "br",
+      Encoding::Zstd => "zst",
+      Encoding::Gzip => "gz",
+    }
+  }
+
+  fn from_extension(extension: &str) -> Option<Self> {
+    Encoding::ALL
+      .into_iter()
+      .find(|encoding| encoding.extension() == extension)
+  }
+}
+
+/// Relative location of an object, e.g. `ab/cdef…` for the id `abcdef…`.
+fn object_key(id: &[u8]) -> (String, String) {
+  (hex::encode(&id[..1]), hex::encode(&id[1..]))
+}
+
+/// Relative location of a variant, e.g. `ab/cdef….br`. The extension keeps
+/// them from being listed as objects.
+fn variant_key(id: &[u8], encoding: Encoding) -> (String, String) {
+  let (dir, name) = object_key(id);
+  (dir, format!("{}.{}", name, encoding.extension()))
+}
+
+fn parse_object_key(dir: &str, name: &str) -> Option<Vec<u8>> {
+  if dir.len() != 2 {
+    return None;
+  }
+
+  hex::decode(format!("{}{}", dir, name)).ok()
+}
+
+fn parse_variant_key(dir: &str, name: &str) -> Option<(Vec<u8>, Encoding)> {
+  let (name, extension) = name.rsplit_once('.')?;
+  let encoding = Encoding::from_extension(extension)?;
+
+  Some((parse_object_key(dir, name)?, encoding))
+}
@@ -0,0 +1,167 @@
+use std::io::{ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::staging::{stage, stage_content, STAGING_DIR};
+use crate::{
+  object_key, parse_object_key, parse_variant_key, variant_key, ByteStream, Encoding, ObjectStore,
+};
+
+/// Stores objects as `root_dir/ab/cdef…` and their variants next to them.
+pub struct LocalStore {
+  root_dir: PathBuf,
+}
+
+impl LocalStore {
+  pub fn new(root_dir: PathBuf) -> Self {
+    Self { root_dir }
+  }
+
+  fn path(&self, id: &[u8]) -> PathBuf {
+    let (dir, name) = object_key(id);
+    self.root_dir.join(dir).join(name)
+  }
+
+  fn variant_path(&self, id: &[u8], encoding: Encoding) -> PathBuf {
+    let (dir, name) = variant_key(id, encoding);
+    self.root_dir.join(dir).join(name)
+  }
+
+  /// The directory and file name of everything stored, staged uploads
+  /// excluded.
+  async fn keys(&self) -> anyhow::Result<Vec<(String, String)>> {
+    let mut keys = Vec::new();
+
+    let mut dirs = tokio::fs::read_dir(&self.root_dir).await?;
+    while let Some(dir) = dirs.next_entry().await? {
+      let dir_name = dir.file_name().to_string_lossy().to_string();
+      if dir_name == STAGING_DIR || !dir.file_type().await?.is_dir() {
+        continue;
+      }
+
+      let mut files = tokio::fs::read_dir(dir.path()).await?;
+      while let Some(file) = files.next_entry().await? {
+        keys.push((
+          dir_name.clone(),
+          file.file_name().to_string_lossy().to_string(),
+        ));
+      }
+    }
+
+    Ok(keys)
+  }
+}
+
+async fn remove_file(path: PathBuf) -> anyhow::Result<()> {
+  match tokio::fs::remove_file(path).await {
+    Ok(()) => Ok(()),
+    Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+    Err(err) => Err(err.into()),
+  }
+}
+
+#[async_trait]
+impl ObjectStore for LocalStore {
+  async fn put(&self, id: &[u8], stream: ByteStream<'_>) -> anyhow::Result<Option<u64>> {
+    let staged = match stage(&self.root_dir, id, stream).await? {
+      Some(staged) => staged,
+      None => return Ok(None),
+    };
+
+    let path = self.path(id);
+    if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let size = staged.size;
+    staged.persist(&path).await?;
+
+    Ok(Some(size))
+  }
+
+  async fn get(
+    &self,
+    id: &[u8],
+    range: Option<Range<u64>>,
+  ) -> anyhow::Result<Option<ByteStream<'static>>> {
+    let mut file = match File::open(self.path(id)).await {
+      Ok(file) => file,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+      Err(err) => return Err(err.into()),
+    };
+
+    let stream = match range {
+      Some(range) => {
+        file.seek(SeekFrom::Start(range.start)).await?;
+        ReaderStream::new(file.take(range.end - range.start)).boxed()
+      }
+      None => ReaderStream::new(file).boxed(),
+    };
+
+    Ok(Some(stream))
+  }
+
+  async fn put_variant(&self, id: &[u8], encoding: Encoding, content: Bytes) -> anyhow::Result<()> {
+    let staged = stage_content(&self.root_dir, &content).await?;
+    staged.persist(&self.variant_path(id, encoding)).await?;
+
+    Ok(())
+  }
+
+  async fn get_variant(
+    &self,
+    id: &[u8],
+    encoding: Encoding,
+  ) -> anyhow::Result<Option<ByteStream<'static>>> {
+    match File::open(self.variant_path(id, encoding)).await {
+      Ok(file) => Ok(Some(ReaderStream::new(file).boxed())),
+      Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  async fn exists(&self, id: &[u8]) -> anyhow::Result<bool> {
+    Ok(tokio::fs::try_exists(self.path(id)).await?)
+  }
+
+  async fn delete(&self, id: &[u8]) -> anyhow::Result<()> {
+    for encoding in Encoding::ALL {
+      remove_file(self.variant_path(id, encoding)).await?;
+    }
+
+    remove_file(self.path(id)).await
+  }
+
+  async fn delete_variant(&self, id: &[u8], encoding: Encoding) -> anyhow::Result<()> {
+    remove_file(self.variant_path(id, encoding)).await
+  }
+
+  async fn list(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+    let keys = self.keys().await?;
+
+    Ok(
+      keys
+        .iter()
+        .filter_map(|(dir, name)| parse_object_key(dir, name))
+        .collect(),
+    )
+  }
+
+  async fn list_variants(&self) -> anyhow::Result<Vec<(Vec<u8>, Encoding)>> {
+    let keys = self.keys().await?;
+
+    Ok(
+      keys
+        .iter()
+        .filter_map(|(dir, name)| parse_variant_key(dir, name))
+        .collect(),
+    )
+  }
+}
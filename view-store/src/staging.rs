@@ -0,0 +1,142 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::ByteStream;
+
+/// Directory inside the root dir where uploads are written before they are
+/// verified and moved to their final location.
+pub(crate) const STAGING_DIR: &str = ".staging";
+
+/// Uploads that have not been touched for this long are assumed to be
+/// abandoned. Other instances sharing the root dir might still be writing to
+/// younger files.
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// A verified upload waiting in the staging directory. The file is removed
+/// again when this is dropped, unless it was persisted.
+pub(crate) struct Staged {
+  pub(crate) path: PathBuf,
+  pub(crate) size: u64,
+  persisted: bool,
+}
+
+impl Staged {
+  fn new(path: PathBuf) -> Self {
+    Self {
+      path,
+      size: 0,
+      persisted: false,
+    }
+  }
+
+  /// Moves the file to its final location.
+  pub(crate) async fn persist(mut self, path: &Path) -> io::Result<()> {
+    tokio::fs::rename(&self.path, path).await?;
+    self.persisted = true;
+    Ok(())
+  }
+}
+
+impl Drop for Staged {
+  fn drop(&mut self) {
+    // a file that can not be removed is left to the sweep
+    if !self.persisted {
+      let _ = std::fs::remove_file(&self.path);
+    }
+  }
+}
+
+/// Writes the stream to a new staging file and syncs it to disk. Returns
+/// `None` if the content does not hash to the id.
+pub(crate) async fn stage(
+  root_dir: &Path,
+  id: &[u8],
+  stream: ByteStream<'_>,
+) -> anyhow::Result<Option<Staged>> {
+  let dir = root_dir.join(STAGING_DIR);
+  tokio::fs::create_dir_all(&dir).await?;
+
+  let path = dir.join(Uuid::new_v4().to_string());
+  let file = File::create(&path).await?;
+  let mut staged = Staged::new(path);
+
+  match receive(file, stream, id).await? {
+    Some(size) => {
+      staged.size = size;
+      Ok(Some(staged))
+    }
+    None => Ok(None),
+  }
+}
+
+/// Writes already verified content to a new staging file and syncs it to
+/// disk.
+pub(crate) async fn stage_content(root_dir: &Path, content: &[u8]) -> io::Result<Staged> {
+  let dir = root_dir.join(STAGING_DIR);
+  tokio::fs::create_dir_all(&dir).await?;
+
+  let path = dir.join(Uuid::new_v4().to_string());
+  let mut file = File::create(&path).await?;
+  let mut staged = Staged::new(path);
+
+  file.write_all(content).await?;
+  file.sync_all().await?;
+  staged.size = content.len() as u64;
+
+  Ok(staged)
+}
+
+async fn receive(
+  mut file: File,
+  mut stream: ByteStream<'_>,
+  id: &[u8],
+) -> anyhow::Result<Option<u64>> {
+  let mut size = 0;
+  let mut hasher = Sha256::new();
+
+  while let Some(chunk) = stream.next().await {
+    let chunk = chunk?;
+    size += chunk.len() as u64;
+    hasher.update(&chunk);
+    file.write_all(chunk.as_ref()).await?;
+  }
+
+  if hasher.finalize().as_slice() != id {
+    return Ok(None);
+  }
+
+  file.sync_all().await?;
+
+  Ok(Some(size))
+}
+
+/// Removes staging files left behind by crashed or aborted uploads and
+/// returns how many were removed.
+pub async fn sweep_staging(root_dir: &Path) -> io::Result<usize> {
+  let dir = root_dir.join(STAGING_DIR);
+  if !tokio::fs::try_exists(&dir).await? {
+    return Ok(0);
+  }
+
+  let now = SystemTime::now();
+  let mut removed = 0;
+
+  let mut read_dir = tokio::fs::read_dir(&dir).await?;
+  while let Some(entry) = read_dir.next_entry().await? {
+    let modified = entry.metadata().await?.modified()?;
+
+    if now.duration_since(modified).unwrap_or_default() >= STALE_AFTER {
+      tokio::fs::remove_file(entry.path()).await?;
+      removed += 1;
+    }
+  }
+
+  Ok(removed)
+}
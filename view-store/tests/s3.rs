@@ -0,0 +1,370 @@
+//! Runs the S3 store against a stub of the few S3 operations it uses, which
+//! keeps the objects of a single bucket in memory.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures_util::{stream, StreamExt};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+
+use view_store::{ByteStream, Encoding, ObjectStore, S3Config, S3Store};
+
+const BUCKET: &str = "view";
+
+/// Keys per page of a listing, small enough to make the store follow the
+/// continuation tokens.
+const PAGE_SIZE: usize = 2;
+
+#[derive(Default)]
+struct Bucket {
+  objects: Mutex<BTreeMap<String, Vec<u8>>>,
+  /// Answer uploads with an error.
+  failing: AtomicBool,
+}
+
+async fn serve(bucket: Arc<Bucket>) -> SocketAddr {
+  let make_service = make_service_fn(move |_| {
+    let bucket = bucket.clone();
+    async move {
+      Ok::<_, Infallible>(service_fn(move |req| {
+        let bucket = bucket.clone();
+        async move { Ok::<_, Infallible>(handle(&bucket, req).await) }
+      }))
+    }
+  });
+
+  let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+  let addr = server.local_addr();
+  tokio::spawn(server);
+
+  addr
+}
+
+async fn handle(bucket: &Bucket, req: Request<Body>) -> Response<Body> {
+  let signed = req
+    .headers()
+    .get("authorization")
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.starts_with("AWS4-HMAC-SHA256 Credential=access/"));
+  if !signed {
+    return status(StatusCode::FORBIDDEN);
+  }
+
+  let Some(key) = req
+    .uri()
+    .path()
+    .strip_prefix(&format!("/{}/", BUCKET))
+    .map(str::to_string)
+  else {
+    return status(StatusCode::NOT_FOUND);
+  };
+
+  match (req.method().clone(), key.as_str()) {
+    (Method::GET, "") => list(bucket, req.uri().query().unwrap_or_default()),
+    (Method::PUT, _) => {
+      if bucket.failing.load(Ordering::SeqCst) {
+        return status(StatusCode::SERVICE_UNAVAILABLE);
+      }
+
+      let payload_hash = req.headers().get("x-amz-content-sha256").cloned().unwrap();
+      let content = hyper::body::to_bytes(req.into_body()).await.unwrap();
+
+      // S3 rejects content not matching the signed hash
+      if payload_hash.as_bytes() != hex::encode(Sha256::digest(&content)).as_bytes() {
+        return status(StatusCode::BAD_REQUEST);
+      }
+
+      bucket.objects.lock().unwrap().insert(key, content.to_vec());
+      status(StatusCode::OK)
+    }
+    (Method::GET, _) => {
+      let Some(content) = bucket.objects.lock().unwrap().get(&key).cloned() else {
+        return status(StatusCode::NOT_FOUND);
+      };
+
+      let range = req
+        .headers()
+        .get("range")
+        .and_then(|value| value.to_str().ok()?.strip_prefix("bytes="))
+        .and_then(|range| range.split_once('-'))
+        .map(|(start, end)| {
+          (
+            start.parse::<usize>().unwrap(),
+            end.parse::<usize>().unwrap(),
+          )
+        });
+
+      match range {
+        Some((start, end)) => Response::builder()
+          .status(StatusCode::PARTIAL_CONTENT)
+          .body(Body::from(content[start..=end].to_vec()))
+          .unwrap(),
+        None => Response::new(Body::from(content)),
+      }
+    }
+    (Method::HEAD, _) if bucket.objects.lock().unwrap().contains_key(&key) => {
+      status(StatusCode::OK)
+    }
+    (Method::HEAD, _) => status(StatusCode::NOT_FOUND),
+    // deleting what does not exist succeeds as well
+    (Method::DELETE, _) => {
+      bucket.objects.lock().unwrap().remove(&key);
+      status(StatusCode::NO_CONTENT)
+    }
+    _ => status(StatusCode::METHOD_NOT_ALLOWED),
+  }
+}
+
+/// `ListObjectsV2`, the continuation token names the last key of the previous
+/// page and contains a `&` to be escaped.
+fn list(bucket: &Bucket, query: &str) -> Response<Body> {
+  let mut list_type = None;
+  let mut after = None;
+
+  for pair in query.split('&') {
+    let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+    let value = urlencoding::decode(value).unwrap().into_owned();
+
+    match key {
+      "list-type" => list_type = Some(value),
+      "continuation-token" => after = Some(value.strip_prefix("after&").unwrap().to_string()),
+      _ => return status(StatusCode::BAD_REQUEST),
+    }
+  }
+
+  if list_type.as_deref() != Some("2") {
+    return status(StatusCode::BAD_REQUEST);
+  }
+
+  let objects = bucket.objects.lock().unwrap();
+  let keys = objects
+    .keys()
+    .filter(|key| after.as_ref().is_none_or(|after| *key > after))
+    .collect::<Vec<_>>();
+  let page = &keys[..keys.len().min(PAGE_SIZE)];
+  let truncated = keys.len() > PAGE_SIZE;
+
+  let mut body = String::from(
+    r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+  );
+  body.push_str(&format!("<IsTruncated>{}</IsTruncated>", truncated));
+  for key in page {
+    body.push_str(&format!("<Contents><Key>{}</Key></Contents>", escape(key)));
+  }
+  if truncated {
+    body.push_str(&format!(
+      "<NextContinuationToken>{}</NextContinuationToken>",
+      escape(&format!("after&{}", page[page.len() - 1]))
+    ));
+  }
+  body.push_str("</ListBucketResult>");
+
+  Response::new(Body::from(body))
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+  Response::builder()
+    .status(status)
+    .body(Body::empty())
+    .unwrap()
+}
+
+async fn store() -> (S3Store, Arc<Bucket>, PathBuf) {
+  let bucket = Arc::new(Bucket::default());
+  let addr = serve(bucket.clone()).await;
+
+  let root_dir = std::env::temp_dir().join(format!("view-store-{}", Uuid::new_v4()));
+
+  let config = S3Config {
+    endpoint: Url::parse(&format!("http://{}", addr)).unwrap(),
+    bucket: BUCKET.to_string(),
+    region: "us-east-1".to_string(),
+    access_key: "access".to_string(),
+    secret_key: "secret".to_string(),
+    path_style: true,
+  };
+
+  (S3Store::new(config, root_dir.clone()), bucket, root_dir)
+}
+
+fn id(content: &[u8]) -> Vec<u8> {
+  Sha256::digest(content).to_vec()
+}
+
+fn chunks(content: &'static [u8]) -> ByteStream<'static> {
+  stream::iter(content.chunks(3).map(|chunk| Ok(Bytes::from_static(chunk)))).boxed()
+}
+
+async fn read(stream: Option<ByteStream<'static>>) -> Option<Vec<u8>> {
+  let mut stream = stream?;
+  let mut content = Vec::new();
+
+  while let Some(chunk) = stream.next().await {
+    content.extend_from_slice(&chunk.unwrap());
+  }
+
+  Some(content)
+}
+
+async fn staged_files(root_dir: &Path) -> usize {
+  let Ok(mut dir) = tokio::fs::read_dir(root_dir.join(".staging")).await else {
+    return 0;
+  };
+
+  let mut count = 0;
+  while dir.next_entry().await.unwrap().is_some() {
+    count += 1;
+  }
+  count
+}
+
+#[tokio::test]
+async fn puts_and_gets_objects() {
+  let (store, bucket, root_dir) = store().await;
+  let content = b"hello world";
+  let id = id(content);
+
+  assert_eq!(store.put(&id, chunks(content)).await.unwrap(), Some(11));
+  assert!(store.exists(&id).await.unwrap());
+  assert_eq!(
+    bucket.objects.lock().unwrap().keys().collect::<Vec<_>>(),
+    [&format!(
+      "{}/{}",
+      hex::encode(&id[..1]),
+      hex::encode(&id[1..])
+    )]
+  );
+
+  assert_eq!(
+    read(store.get(&id, None).await.unwrap()).await.as_deref(),
+    Some(&content[..])
+  );
+  assert_eq!(
+    read(store.get(&id, Some(6..11)).await.unwrap())
+      .await
+      .as_deref(),
+    Some(&b"world"[..])
+  );
+  assert_eq!(
+    read(store.get(&id, Some(0..1)).await.unwrap())
+      .await
+      .as_deref(),
+    Some(&b"h"[..])
+  );
+
+  assert_eq!(staged_files(&root_dir).await, 0);
+}
+
+#[tokio::test]
+async fn rejects_content_not_matching_the_id() {
+  let (store, bucket, root_dir) = store().await;
+  let id = id(b"expected");
+
+  assert_eq!(
+    store.put(&id, chunks(b"something else")).await.unwrap(),
+    None
+  );
+  assert!(!store.exists(&id).await.unwrap());
+  assert!(read(store.get(&id, None).await.unwrap()).await.is_none());
+  assert!(bucket.objects.lock().unwrap().is_empty());
+  assert_eq!(staged_files(&root_dir).await, 0);
+}
+
+#[tokio::test]
+async fn returns_upload_errors() {
+  let (store, bucket, root_dir) = store().await;
+  let id = id(b"content");
+
+  bucket.failing.store(true, Ordering::SeqCst);
+  let err = store.put(&id, chunks(b"content")).await.unwrap_err();
+
+  assert!(err.to_string().contains("503"), "{}", err);
+  assert_eq!(staged_files(&root_dir).await, 0);
+}
+
+#[tokio::test]
+async fn lists_objects_and_variants_across_pages() {
+  let (store, _, _) = store().await;
+  let contents: [&'static [u8]; 3] = [b"one", b"two", b"three"];
+
+  let mut ids = Vec::new();
+  for content in contents {
+    let id = id(content);
+    store.put(&id, chunks(content)).await.unwrap();
+    ids.push(id);
+  }
+
+  store
+    .put_variant(&ids[0], Encoding::Gzip, Bytes::from_static(b"gz"))
+    .await
+    .unwrap();
+  store
+    .put_variant(&ids[0], Encoding::Brotli, Bytes::from_static(b"br"))
+    .await
+    .unwrap();
+
+  let mut listed = store.list().await.unwrap();
+  listed.sort();
+  ids.sort();
+  assert_eq!(listed, ids);
+
+  let mut variants = store.list_variants().await.unwrap();
+  variants.sort_by_key(|(_, encoding)| encoding.name());
+  assert_eq!(
+    variants,
+    [(id(b"one"), Encoding::Brotli), (id(b"one"), Encoding::Gzip)]
+  );
+}
+
+#[tokio::test]
+async fn deletes_objects_with_their_variants() {
+  let (store, bucket, _) = store().await;
+  let id = id(b"content");
+
+  store.put(&id, chunks(b"content")).await.unwrap();
+  for encoding in [Encoding::Brotli, Encoding::Zstd] {
+    store
+      .put_variant(&id, encoding, Bytes::from_static(b"compressed"))
+      .await
+      .unwrap();
+  }
+
+  assert_eq!(
+    read(store.get_variant(&id, Encoding::Zstd).await.unwrap())
+      .await
+      .as_deref(),
+    Some(&b"compressed"[..])
+  );
+  assert!(read(store.get_variant(&id, Encoding::Gzip).await.unwrap())
+    .await
+    .is_none());
+
+  store.delete_variant(&id, Encoding::Zstd).await.unwrap();
+  assert!(read(store.get_variant(&id, Encoding::Zstd).await.unwrap())
+    .await
+    .is_none());
+  assert!(store.exists(&id).await.unwrap());
+
+  store.delete(&id).await.unwrap();
+  assert!(!store.exists(&id).await.unwrap());
+  assert!(bucket.objects.lock().unwrap().is_empty());
+
+  // already gone
+  store.delete(&id).await.unwrap();
+}
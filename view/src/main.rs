@@ -3,26 +3,33 @@ use std::future::Future;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use hyper::service::Service;
-use sea_orm::{Database, DatabaseConnection};
+use sea_orm::Database;
 use sea_orm_migration::MigratorTrait;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
+use tokio::time::{interval, Duration};
 use tower::ServiceBuilder;
-use tracing::{info, Level};
+use tracing::{error, info, Level};
 use tracing_subscriber::FmtSubscriber;
 use url::Url;
 
-use view_management::{ManagementState, router};
+use view_management::{
+  check_consistency, collect_garbage, FsckReport, GcReport, ManagementState, RetentionPolicy,
+  router,
+};
 use view_migration::Migrator;
-use view_serve::FileService;
+use view_serve::{BlobCache, BlobCacheStats, FileService, ManifestCache};
+use view_store::{LocalStore, ObjectStore, S3Config, S3Store, sweep_staging};
 
 pub struct MakeSvc {
-  root_dir: PathBuf,
-  db: DatabaseConnection,
+  blobs: Arc<BlobCache>,
+  manifests: Arc<ManifestCache>,
+  index_names: Arc<Vec<String>>,
 }
 
 impl<T> Service<T> for MakeSvc {
@@ -36,22 +43,23 @@ impl<T> Service<T> for MakeSvc {
 
   fn call(&mut self, _: T) -> Self::Future {
     let src = FileService {
-      root_dir: self.root_dir.clone(),
-      db: self.db.clone(),
+      blobs: self.blobs.clone(),
+      manifests: self.manifests.clone(),
+      index_names: self.index_names.clone(),
     };
 
-    let fut = async {
-      Ok(
-        ServiceBuilder::new()
-          // .layer(CompressionLayer::new())
-          .service(src),
-      )
-    };
+    let fut = async { Ok(ServiceBuilder::new().service(src)) };
 
     Box::pin(fut)
   }
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum StoreKind {
+  Local,
+  S3,
+}
+
 #[derive(Parser)]
 #[clap(version)]
 struct Cli {
@@ -65,23 +73,101 @@ struct Cli {
   db_pass: Option<String>,
   #[clap(long, env = "VIEW_DB_PASS_PATH", required_unless_present = "db_pass")]
   db_pass_path: Option<PathBuf>,
+  /// Object storage for the local store, staging area for all stores
   #[clap(short, long, env = "VIEW_ROOT_DIR")]
   root_dir: PathBuf,
+  #[clap(long, env = "VIEW_STORE", value_enum, default_value = "local")]
+  store: StoreKind,
+  #[clap(long, env = "VIEW_S3_ENDPOINT", required_if_eq("store", "s3"))]
+  s3_endpoint: Option<Url>,
+  #[clap(long, env = "VIEW_S3_BUCKET", required_if_eq("store", "s3"))]
+  s3_bucket: Option<String>,
+  #[clap(long, env = "VIEW_S3_REGION", default_value = "us-east-1")]
+  s3_region: String,
+  #[clap(long, env = "VIEW_S3_ACCESS_KEY", required_if_eq("store", "s3"))]
+  s3_access_key: Option<String>,
+  #[clap(long, env = "VIEW_S3_SECRET_KEY")]
+  s3_secret_key: Option<String>,
+  #[clap(long, env = "VIEW_S3_SECRET_KEY_PATH")]
+  s3_secret_key_path: Option<PathBuf>,
+  /// Address buckets as endpoint/bucket instead of bucket.endpoint
+  #[clap(long, env = "VIEW_S3_PATH_STYLE")]
+  s3_path_style: bool,
   #[clap(short, long, env = "VIEW_SERVE_ADDR", default_value = "0.0.0.0:8080")]
   serve_addr: SocketAddr,
+  /// Files serving requests for their directory, in order of preference
+  #[clap(
+    long = "index-name",
+    env = "VIEW_INDEX_NAMES",
+    value_delimiter = ',',
+    default_value = "index.html"
+  )]
+  index_names: Vec<String>,
+  /// Seconds a cached environment is served before checking whether it
+  /// changed, e.g. got another commit published
+  #[clap(long, env = "VIEW_MANIFEST_REVALIDATE", default_value_t = 2)]
+  manifest_revalidate: u64,
+  /// Serve every commit at <commit id prefix>.<preview domain>
+  #[clap(long, env = "VIEW_PREVIEW_DOMAIN")]
+  preview_domain: Option<String>,
+  /// Bytes of object content kept in memory, 0 disables the cache
+  #[clap(long, env = "VIEW_BLOB_CACHE_SIZE", default_value_t = 64 * 1024 * 1024)]
+  blob_cache_size: u64,
+  /// Largest object in bytes that is kept in memory
+  #[clap(long, env = "VIEW_BLOB_CACHE_MAX_OBJECT_SIZE", default_value_t = 256 * 1024)]
+  blob_cache_max_object_size: u64,
   #[clap(short, long, env = "VIEW_MGNT_ADDR", default_value = "0.0.0.0:8081")]
   mgnt_addr: SocketAddr,
-  #[clap(short = 't', long, env = "VIEW_MGNT_TOKEN", required_unless_present = "mgnt_token_path")]
+  #[clap(short = 't', long, env = "VIEW_MGNT_TOKEN")]
   mgnt_token: Option<String>,
-  #[clap(long, env = "VIEW_MGNT_TOKEN_PATH", required_unless_present = "mgnt_token")]
+  #[clap(long, env = "VIEW_MGNT_TOKEN_PATH")]
   mgnt_token_path: Option<String>,
+  #[clap(flatten)]
+  retention: RetentionArgs,
+  /// Run the garbage collection every this many seconds while serving
+  #[clap(long, env = "VIEW_GC_INTERVAL", value_parser = clap::value_parser!(u64).range(1..))]
+  gc_interval: Option<u64>,
+  #[clap(subcommand)]
+  command: Option<Command>,
+}
+
+#[derive(Args)]
+struct RetentionArgs {
+  /// Number of most recently deployed commits to keep per environment
+  #[clap(long, env = "VIEW_GC_KEEP_COMMITS", default_value_t = 10)]
+  gc_keep_commits: usize,
+  /// Keep every commit created within this many days
+  #[clap(long, env = "VIEW_GC_KEEP_DAYS", default_value_t = 7)]
+  gc_keep_days: u32,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Delete commits outside the retention policy and unreferenced objects
+  Gc {
+    /// Only report what would be deleted
+    #[clap(long)]
+    dry_run: bool,
+  },
+  /// Check that the database and the object store agree
+  Fsck {
+    /// Print the report as JSON
+    #[clap(long)]
+    json: bool,
+    /// Delete orphaned content and reset broken objects, so they get uploaded again,
+    /// and compress objects lacking variants
+    #[clap(long)]
+    repair: bool,
+  },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
   let cli = Cli::parse();
 
+  // keeps stdout free for reports, like the JSON output of fsck
   let subscriber = FmtSubscriber::builder()
+    .with_writer(std::io::stderr)
     .with_max_level(Level::INFO)
     .compact()
     .finish();
@@ -122,21 +208,106 @@ async fn main() -> anyhow::Result<()> {
 
   Migrator::up(&db, None).await?;
 
+  let removed = sweep_staging(&cli.root_dir).await?;
+  if removed > 0 {
+    info!("Removed {} stale staging files", removed);
+  }
+
+  let store: Arc<dyn ObjectStore> = match cli.store {
+    StoreKind::Local => Arc::new(LocalStore::new(cli.root_dir.clone())),
+    StoreKind::S3 => {
+      let secret_key = match (cli.s3_secret_key_path, cli.s3_secret_key) {
+        (Some(path), _) => {
+          let mut file = File::open(path).await?;
+          let mut buf = String::new();
+          file.read_to_string(&mut buf).await?;
+          buf
+        }
+        (None, Some(secret_key)) => secret_key,
+        (None, None) => anyhow::bail!("The S3 store requires --s3-secret-key or --s3-secret-key-path"),
+      };
+
+      let config = S3Config {
+        endpoint: cli.s3_endpoint.unwrap(),
+        bucket: cli.s3_bucket.unwrap(),
+        region: cli.s3_region,
+        access_key: cli.s3_access_key.unwrap(),
+        secret_key,
+        path_style: cli.s3_path_style,
+      };
+
+      Arc::new(S3Store::new(config, cli.root_dir.clone()))
+    }
+  };
+
+  let policy = RetentionPolicy {
+    keep_commits: cli.retention.gc_keep_commits,
+    keep_days: cli.retention.gc_keep_days,
+  };
+
+  match cli.command {
+    Some(Command::Gc { dry_run }) => {
+      let report = collect_garbage(&db, &*store, &policy, dry_run).await?;
+      log_gc_report(&report, dry_run);
+      return Ok(());
+    }
+    Some(Command::Fsck { json, repair }) => {
+      let report = check_consistency(&db, &*store, repair).await?;
+
+      if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+      } else {
+        print_fsck_report(&report);
+      }
+
+      if !report.is_clean() && !repair {
+        anyhow::bail!("Found inconsistencies, run with --repair to fix them");
+      }
+
+      return Ok(());
+    }
+    None => {}
+  }
+
+  let preview_domain = cli
+    .preview_domain
+    .map(|domain| domain.trim().trim_matches('.').to_ascii_lowercase());
+
   let state = ManagementState {
     db: db.clone(),
-    root_dir: cli.root_dir.clone(),
+    store: store.clone(),
+    preview_domain: preview_domain.clone(),
   };
 
-  let token = match cli.mgnt_token_path {
-    Some(path) => {
+  let token = match (cli.mgnt_token_path, cli.mgnt_token) {
+    (Some(path), _) => {
       let mut file = File::open(path).await?;
       let mut buf = String::new();
       file.read_to_string(&mut buf).await?;
       buf
     }
-    None => cli.mgnt_token.unwrap(),
+    (None, Some(token)) => token,
+    (None, None) => anyhow::bail!("Serving requires --mgnt-token or --mgnt-token-path"),
   };
 
+  if let Some(seconds) = cli.gc_interval {
+    let db = db.clone();
+    let store = store.clone();
+
+    tokio::spawn(async move {
+      let mut interval = interval(Duration::from_secs(seconds));
+
+      loop {
+        interval.tick().await;
+
+        match collect_garbage(&db, &*store, &policy, false).await {
+          Ok(report) => log_gc_report(&report, false),
+          Err(err) => error!("Garbage collection failed: {:?}", err),
+        }
+      }
+    });
+  }
+
   tokio::spawn(async move {
     let mgnt = hyper::Server::bind(&cli.mgnt_addr).serve(router(state, &token));
 
@@ -145,9 +316,50 @@ async fn main() -> anyhow::Result<()> {
     mgnt.await.unwrap();
   });
 
+  let blobs = Arc::new(BlobCache::new(
+    store,
+    cli.blob_cache_size,
+    cli.blob_cache_max_object_size,
+  ));
+
+  if cli.blob_cache_size > 0 {
+    let blobs = blobs.clone();
+
+    tokio::spawn(async move {
+      let mut interval = interval(Duration::from_secs(60));
+      let mut last = None;
+
+      // the first tick completes immediately
+      interval.tick().await;
+
+      loop {
+        interval.tick().await;
+
+        let stats = blobs.stats();
+        let unchanged = last.is_some_and(|last: BlobCacheStats| {
+          last.hits == stats.hits && last.misses == stats.misses
+        });
+        if unchanged {
+          continue;
+        }
+
+        info!(
+          "Blob cache: {} hits, {} misses, {} contents ({} bytes)",
+          stats.hits, stats.misses, stats.entries, stats.bytes
+        );
+        last = Some(stats);
+      }
+    });
+  }
+
   let file_service = MakeSvc {
-    root_dir: cli.root_dir,
-    db,
+    blobs,
+    manifests: Arc::new(ManifestCache::new(
+      db,
+      Duration::from_secs(cli.manifest_revalidate),
+      preview_domain,
+    )),
+    index_names: Arc::new(cli.index_names),
   };
 
   let service = ServiceBuilder::new().service(file_service);
@@ -159,3 +371,35 @@ async fn main() -> anyhow::Result<()> {
 
   Ok(())
 }
+
+fn log_gc_report(report: &GcReport, dry_run: bool) {
+  info!(
+    "{} {} commits with {} files and {} objects ({} bytes)",
+    if dry_run { "Would delete" } else { "Deleted" },
+    report.commits,
+    report.files,
+    report.objects,
+    report.bytes
+  );
+}
+
+fn print_fsck_report(report: &FsckReport) {
+  let categories = [
+    ("Not uploaded", &report.not_uploaded),
+    ("Unrecorded", &report.unrecorded),
+    ("Missing", &report.missing),
+    ("Corrupted", &report.corrupted),
+    ("Size mismatch", &report.size_mismatch),
+    ("Orphaned", &report.orphaned),
+    ("Orphaned variants", &report.orphaned_variants),
+    ("Missing variants", &report.missing_variants),
+    ("Uncompressed", &report.uncompressed),
+  ];
+
+  for (name, ids) in categories {
+    println!("{}: {}", name, ids.len());
+    for id in ids {
+      println!("  {}", id);
+    }
+  }
+}